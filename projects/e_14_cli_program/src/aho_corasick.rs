@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+
+use crate::fold;
+
+// Aho-Corasick finds many literal patterns in a single pass over the text.
+// The patterns are put in a trie (a tree where each edge is one byte), and every node gets
+// a failure link: the longest proper suffix of the node's path that is also a path in the trie.
+// When the next byte has no edge we follow failure links instead of going back in the text,
+// so every byte of the text is looked at once no matter how many patterns there are.
+
+struct State {
+    next: Vec<(u8, usize)>, // Sorted by byte so we can use a binary search.
+    fail: usize,
+    // The patterns that end at this state, including the ones reachable through failure links.
+    outputs: Vec<usize>,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            next: Vec::new(),
+            fail: 0,
+            outputs: Vec::new(),
+        }
+    }
+
+    fn next(&self, byte: u8) -> Option<usize> {
+        self.next
+            .binary_search_by_key(&byte, |&(b, _)| b)
+            .ok()
+            .map(|i| self.next[i].1)
+    }
+}
+
+/// A pattern found in the text, `pattern` is its index in the list given to `AhoCorasick::build`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternMatch {
+    pub pattern: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The lines with at least one pattern, and which patterns were found in each of them.
+#[derive(Debug, PartialEq)]
+pub struct LineHits<'a> {
+    pub line_number: usize,
+    pub line: &'a str,
+    pub patterns: Vec<usize>, // Sorted and without duplicates.
+}
+
+pub struct AhoCorasick {
+    states: Vec<State>,
+    lengths: Vec<usize>, // The length in bytes of every (folded) pattern.
+    ignore_case: bool,
+}
+
+impl AhoCorasick {
+    /// Builds the automaton for the patterns, with `ignore_case` the patterns and the text are case folded.
+    pub fn build<S: AsRef<str>>(patterns: &[S], ignore_case: bool) -> AhoCorasick {
+        let mut states = vec![State::new()];
+        let mut lengths = Vec::with_capacity(patterns.len());
+
+        // First we build the trie, one path from the root for every pattern.
+        for (index, pattern) in patterns.iter().enumerate() {
+            let pattern = if ignore_case {
+                fold::fold(pattern.as_ref())
+            } else {
+                pattern.as_ref().to_string()
+            };
+            let mut current = 0;
+            for &byte in pattern.as_bytes() {
+                current = match states[current].next(byte) {
+                    Some(next) => next,
+                    None => {
+                        states.push(State::new());
+                        let next = states.len() - 1;
+                        let edges = &mut states[current].next;
+                        let position = edges.partition_point(|&(b, _)| b < byte);
+                        edges.insert(position, (byte, next));
+                        next
+                    }
+                };
+            }
+            states[current].outputs.push(index);
+            lengths.push(pattern.len());
+        }
+
+        // Then the failure links, in breadth-first order so the link of a shorter path is ready before it is needed.
+        let mut queue: VecDeque<usize> = states[0].next.iter().map(|&(_, s)| s).collect();
+        while let Some(current) = queue.pop_front() {
+            for (byte, child) in states[current].next.clone() {
+                let mut fail = states[current].fail;
+                let child_fail = loop {
+                    if let Some(next) = states[fail].next(byte) {
+                        break next;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = states[fail].fail;
+                };
+                states[child].fail = child_fail;
+                let inherited = states[child_fail].outputs.clone();
+                states[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick {
+            states,
+            lengths,
+            ignore_case,
+        }
+    }
+
+    // Returns every occurrence of every pattern in the bytes, overlapping ones included.
+    fn find_all(&self, text: &[u8]) -> Vec<PatternMatch> {
+        let mut found = Vec::new();
+        let mut current = 0;
+        // Empty patterns end at the root, they match at the start of the text.
+        for &pattern in &self.states[0].outputs {
+            found.push(PatternMatch {
+                pattern,
+                start: 0,
+                end: 0,
+            });
+        }
+        for (i, &byte) in text.iter().enumerate() {
+            current = loop {
+                if let Some(next) = self.states[current].next(byte) {
+                    break next;
+                }
+                if current == 0 {
+                    break 0;
+                }
+                current = self.states[current].fail;
+            };
+            for &pattern in &self.states[current].outputs {
+                found.push(PatternMatch {
+                    pattern,
+                    start: i + 1 - self.lengths[pattern],
+                    end: i + 1,
+                });
+            }
+        }
+        found
+    }
+
+    /// Returns the non-overlapping matches in the text, from left to right.
+    /// When several patterns start at the same place the longest one wins, like in grep.
+    pub fn find_iter(&self, text: &str) -> Vec<PatternMatch> {
+        let mut found = self.find_all_matches(text);
+        found.sort_by_key(|m| (m.start, std::cmp::Reverse(m.end)));
+
+        let mut selected: Vec<PatternMatch> = Vec::new();
+        for m in found {
+            let free = selected.last().is_none_or(|last| {
+                m.start >= last.end && !(m.start == m.end && m.start == last.start)
+            });
+            if free {
+                selected.push(m);
+            }
+        }
+        selected
+    }
+
+    // Searches the folded text and maps the matches back to the original text.
+    // A match that starts or ends in the middle of a character's folding (like half of the "ss" of 'ß') doesn't count.
+    fn find_all_folded(&self, text: &str) -> Vec<PatternMatch> {
+        let (folded, origins) = fold::fold_with_offsets(text);
+        let origin = |index: usize| {
+            if index == folded.len() {
+                Some(text.len())
+            } else {
+                origins[index]
+            }
+        };
+        self.find_all(folded.as_bytes())
+            .into_iter()
+            .filter_map(|m| {
+                Some(PatternMatch {
+                    pattern: m.pattern,
+                    start: origin(m.start)?,
+                    end: origin(m.end)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Searches every line of the contents and reports which patterns were found in each line.
+    pub fn search<'a>(&self, contents: &'a str) -> Vec<LineHits<'a>> {
+        contents
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let mut patterns: Vec<usize> = self
+                    .find_all_matches(line)
+                    .into_iter()
+                    .map(|m| m.pattern)
+                    .collect();
+                if patterns.is_empty() {
+                    return None;
+                }
+                patterns.sort_unstable();
+                patterns.dedup();
+                Some(LineHits {
+                    line_number: index + 1,
+                    line,
+                    patterns,
+                })
+            })
+            .collect()
+    }
+
+    // Unlike find_iter, this keeps the overlapping matches, so a pattern that is inside a longer one is still reported.
+    fn find_all_matches(&self, text: &str) -> Vec<PatternMatch> {
+        if self.ignore_case {
+            self.find_all_folded(text)
+        } else {
+            self.find_all(text.as_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_every_pattern() {
+        let ac = AhoCorasick::build(&["he", "she", "his", "hers"], false);
+        let found: Vec<_> = ac
+            .find_all(b"ushers")
+            .into_iter()
+            .map(|m| (m.pattern, m.start, m.end))
+            .collect();
+        assert_eq!(found, vec![(1, 1, 4), (0, 2, 4), (3, 2, 6)]);
+        // Without overlaps "she" wins because it starts first.
+        assert_eq!(
+            ac.find_iter("ushers"),
+            vec![PatternMatch {
+                pattern: 1,
+                start: 1,
+                end: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn longest_pattern_wins_at_the_same_start() {
+        let ac = AhoCorasick::build(&["no", "nobody"], false);
+        let found: Vec<_> = ac
+            .find_iter("nobody knows")
+            .into_iter()
+            .map(|m| (m.pattern, m.start, m.end))
+            .collect();
+        assert_eq!(found, vec![(1, 0, 6), (0, 8, 10)]);
+    }
+
+    #[test]
+    fn reports_patterns_per_line() {
+        let ac = AhoCorasick::build(&["FROG", "bog", "nobody"], true);
+        let contents = "I'm nobody! Who are you?\nHow public, like a frog\nTo an admiring bog!";
+        let hits = ac.search(contents);
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].patterns, vec![2]);
+        assert_eq!(hits[1].patterns, vec![0]);
+        assert_eq!(hits[2].patterns, vec![1]);
+    }
+
+    #[test]
+    fn ignore_case_maps_offsets_back() {
+        let ac = AhoCorasick::build(&["strasse"], true);
+        let found = ac.find_iter("die Straße");
+        assert_eq!((found[0].start, found[0].end), (4, 11));
+    }
+}
@@ -0,0 +1,934 @@
+use std::{
+    env,
+    error::Error,
+    fmt, fs, io, iter,
+    path::{Path, PathBuf},
+};
+
+use crate::{glob::Glob, ignore::Filter, stream::ReadOptions, MinigrepError, SearchOptions};
+
+/// The file with the default options, read from the current directory or else from the home directory.
+pub const CONFIG_FILE: &str = ".minigreprc";
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] <QUERY> [PATH]...
+       minigrep [OPTIONS] -e <PATTERN>... [PATH]...
+       minigrep [OPTIONS] -f <FILE> [PATH]...
+       minigrep index [OPTIONS] [DIR]...
+
+Searches for QUERY in every PATH, directories are searched recursively.
+With no PATH, or when PATH is -, standard input is searched.
+`minigrep index` builds or updates a trigram index in every DIR (the current directory by default),
+searches in DIR then skip the files that can't match. Use -e index to search for the word index.
+Default options can be written in a .minigreprc file in the current directory or the home directory,
+one per line like --context=2. The command line wins over the environment, which wins over the file.
+
+Options:
+  -e, --regexp <PATTERN>       Search for PATTERN, can be given more than once
+  -f, --file <FILE>            Search for every pattern in FILE, one per line
+  -i, --ignore-case            Ignore case (also enabled by the IGNORE_CASE env var)
+      --no-ignore-case         Don't ignore case, even if IGNORE_CASE is set
+  -E, --regex                  Treat QUERY as a regular expression
+  -v, --invert-match           Select the lines that don't match
+  -w, --word-regexp            Only match whole words
+  -x, --line-regexp            Only match whole lines
+      --fuzzy <K>              Also match text up to K typos (edits) away from QUERY, the closest lines first
+  -n, --line-number            Print the line number of every match
+  -c, --count                  Only print the number of matching lines per file
+  -l, --files-with-matches     Only print the names of the files with matches
+  -o, --only-matching          Only print the matched text and its byte offset
+      --json                   Print every match, context line and file as a JSON object, one per line
+  -A, --after-context <NUM>    Print NUM lines after every match
+  -B, --before-context <NUM>   Print NUM lines before every match
+  -C, --context <NUM>          Print NUM lines before and after every match
+      --replace <TEXT>         Show a diff of the files with every match replaced by TEXT,
+                               $1, $2... in TEXT are the groups of the regex and $0 the whole match
+      --write                  With --replace, change the files instead of showing a diff
+      --include <GLOB>         Only search the files in directories that match GLOB, like '*.rs'
+      --exclude <GLOB>         Skip the files and directories that match GLOB, like 'target/**'
+      --no-ignore              Don't skip what .gitignore and .ignore files exclude
+  -a, --text                   Print the matching lines of binary files too
+      --lossy                  Decode UTF-16 files with a BOM and Latin-1 lines instead of treating them as binary
+      --watch                  Keep running and print the matching lines added to the files, like tail -f
+  -j, --jobs <NUM>             Search NUM files at the same time, at most 1024 (default: one per CPU core)
+      --color[=WHEN]           Color the matches, WHEN is auto (the default), always or never
+      --no-config              Don't read the .minigreprc file
+      --debug-config           Print where every option came from (the command line, the environment or .minigreprc)
+  -h, --help                   Print this help
+  -V, --version                Print the version
+      --                       Everything after this is a QUERY or PATH, even if it starts with '-'";
+
+// What run prints for the matches of each file, like grep's -c, -l and -o options.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputMode {
+    #[default]
+    Lines,
+    Count,
+    FilesWithMatches,
+    OnlyMatching,
+    Json,
+}
+
+// auto only colors the output when stdout is a terminal, so colors don't end up in files or pipes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub query: String,
+    pub patterns: Vec<String>, // Patterns given with -e, when there are any QUERY is a path.
+    pub pattern_files: Vec<String>, // Files given with -f, with one pattern per line.
+    pub paths: Vec<String>,    // Files or directories, directories are searched recursively.
+    pub ignore_case: bool,
+    pub regex: bool,
+    pub invert_match: bool,
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    pub line_number: bool,
+    pub mode: OutputMode,
+    pub before_context: usize, // Lines to print before each match (-B).
+    pub after_context: usize,  // Lines to print after each match (-A).
+    pub color: ColorChoice,
+    pub replace: Option<String>, // The text that replaces every match (--replace).
+    pub write: bool,             // Write the replacements to the files instead of showing a diff.
+    pub include: Vec<Glob>, // Only the files inside directories that match one of these are searched.
+    pub exclude: Vec<Glob>,
+    pub no_ignore: bool,              // Don't read .gitignore and .ignore files.
+    pub text: bool,                   // Treat binary files as text (-a).
+    pub lossy: bool,                  // Decode files that aren't UTF-8 (--lossy).
+    pub jobs: Option<usize>, // Threads for searching many files (-j), None means one per CPU core.
+    pub fuzzy: Option<usize>, // The number of edits a match can be away from the query (--fuzzy).
+    pub index: bool, // minigrep index: update the trigram index of the paths instead of searching them.
+    pub watch: bool, // Print the matches of the lines added to the files until stopped (--watch).
+    pub debug_config: bool, // Print where every option came from (--debug-config).
+    pub config_file: Option<PathBuf>, // The .minigreprc that was read, if any.
+    pub origins: Vec<Origin>, // The options that were set, in the order they were applied.
+}
+
+/// The layer an option came from. The command line wins over the environment, which wins over the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    File { path: PathBuf, line: usize },
+    Env(&'static str),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::File { path, line } => write!(f, "{} line {line}", path.display()),
+            Source::Env(name) => write!(f, "environment variable {name}"),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The value of an option and the layer that set it, for --debug-config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub setting: String,
+    pub value: String,
+    pub source: Source,
+}
+
+// Help and Version aren't really errors, but like an error they stop the parsing
+// and main decides what to print and how to exit.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    MissingQuery,
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+    },
+    NeedsOption {
+        option: String,
+        needed: String,
+    },
+    Conflict {
+        option: String,
+        other: String,
+    },
+    // A problem with the config file, on a line of it or with the whole file when line is None.
+    ConfigFile {
+        path: String,
+        line: Option<usize>,
+        message: String,
+    },
+    Help,
+    Version,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "Query not found!"),
+            ConfigError::UnknownOption(option) => write!(f, "Unknown option '{option}'!"),
+            ConfigError::MissingValue(option) => write!(f, "Option '{option}' needs a value!"),
+            ConfigError::UnexpectedValue(option) => {
+                write!(f, "Option '{option}' doesn't take a value!")
+            }
+            ConfigError::InvalidValue { option, value } => {
+                write!(f, "Invalid value '{value}' for option '{option}'!")
+            }
+            ConfigError::NeedsOption { option, needed } => {
+                write!(f, "Option '{option}' can only be used with '{needed}'!")
+            }
+            ConfigError::Conflict { option, other } => {
+                write!(
+                    f,
+                    "Options '{option}' and '{other}' can't be used together!"
+                )
+            }
+            ConfigError::ConfigFile {
+                path,
+                line: Some(line),
+                message,
+            } => write!(f, "{path} line {line}: {message}"),
+            ConfigError::ConfigFile {
+                path,
+                line: None,
+                message,
+            } => write!(f, "{path}: {message}"),
+            ConfigError::Help => write!(f, "{USAGE}"),
+            ConfigError::Version => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+// Every short option is another name for a long option, so the parser only needs to handle the long ones.
+fn long_name(short: char) -> Option<&'static str> {
+    Some(match short {
+        'e' => "regexp",
+        'f' => "file",
+        'i' => "ignore-case",
+        'E' => "regex",
+        'v' => "invert-match",
+        'w' => "word-regexp",
+        'x' => "line-regexp",
+        'n' => "line-number",
+        'c' => "count",
+        'l' => "files-with-matches",
+        'o' => "only-matching",
+        'a' => "text",
+        'j' => "jobs",
+        'A' => "after-context",
+        'B' => "before-context",
+        'C' => "context",
+        'h' => "help",
+        'V' => "version",
+        _ => return None,
+    })
+}
+
+fn takes_value(name: &str) -> bool {
+    matches!(
+        name,
+        "regexp"
+            | "file"
+            | "replace"
+            | "include"
+            | "exclude"
+            | "jobs"
+            | "fuzzy"
+            | "after-context"
+            | "before-context"
+            | "context"
+    )
+}
+
+// These options can have a value after an '=', but they don't take the next argument as their value.
+fn optional_value(name: &str) -> bool {
+    matches!(name, "color" | "colour")
+}
+
+// The most context lines -A, -B and -C accept. More would only be a typo, and it is the size of a buffer.
+const MAX_CONTEXT: usize = 1_000_000;
+// The most threads -j accepts. Every one of them has its own stack, and the system refuses to start too many.
+const MAX_JOBS: usize = 1_024;
+
+fn number(option: &str, value: String, max: usize) -> Result<usize, ConfigError> {
+    match value.parse() {
+        Ok(number) if number <= max => Ok(number),
+        _ => Err(ConfigError::InvalidValue {
+            option: option.to_string(),
+            value,
+        }),
+    }
+}
+
+fn glob(option: &str, value: String) -> Result<Glob, ConfigError> {
+    Glob::build(&value).map_err(|_| ConfigError::InvalidValue {
+        option: option.to_string(),
+        value,
+    })
+}
+
+// The arguments split into options and positional arguments, every option with its value if it has one.
+type Parsed = (Vec<(String, Option<String>)>, Vec<String>);
+
+// Options start with "-" and can go anywhere, everything else is a positional argument.
+// Some options take the next argument as their value, so we can't just filter them out.
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Parsed, ConfigError> {
+    let mut options = Vec::new();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            // Long options can have their value after an '=': --context=2
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let option = format!("--{name}");
+            let value = match (takes_value(name), value) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(args.next().ok_or(ConfigError::MissingValue(option))?),
+                (false, Some(_)) if !optional_value(name) => {
+                    return Err(ConfigError::UnexpectedValue(option))
+                }
+                (false, value) => value,
+            };
+            options.push((name.to_string(), value));
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            // Short options can be combined (-in) and the value can be attached (-A2) or be the next argument (-A 2).
+            for (i, short) in arg.char_indices().skip(1) {
+                let name = long_name(short)
+                    .ok_or_else(|| ConfigError::UnknownOption(format!("-{short}")))?;
+                if takes_value(name) {
+                    let rest = &arg[i + short.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                            .ok_or_else(|| ConfigError::MissingValue(format!("-{short}")))?
+                    } else {
+                        rest.to_string()
+                    };
+                    options.push((name.to_string(), Some(value)));
+                    break;
+                }
+                options.push((name.to_string(), None));
+            }
+        } else {
+            // A lonely "-" is a positional argument too.
+            positional.push(arg);
+        }
+    }
+    Ok((options, positional))
+}
+
+// A config file only has defaults, the options that say what to search or change the files have to be typed every time.
+fn allowed_in_config_file(name: &str) -> bool {
+    !matches!(
+        name,
+        "regexp" | "file" | "replace" | "write" | "watch" | "help" | "version" | "no-config"
+    )
+}
+
+// The .minigreprc of the current directory, or else the one in the home directory.
+fn find_config_file() -> Result<Option<(PathBuf, String)>, ConfigError> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    let candidates = [
+        Some(PathBuf::from(CONFIG_FILE)),
+        home.map(|home| PathBuf::from(home).join(CONFIG_FILE)),
+    ];
+    for path in candidates.into_iter().flatten() {
+        match fs::read_to_string(&path) {
+            Ok(contents) => return Ok(Some((path, contents))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(ConfigError::ConfigFile {
+                    path: path.display().to_string(),
+                    line: None,
+                    message: e.to_string(),
+                })
+            }
+        }
+    }
+    Ok(None)
+}
+
+impl Config {
+    /// Builds the config from the arguments, the IGNORE_CASE environment variable and the .minigreprc file,
+    /// in this order of precedence.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        Config::layered(args, find_config_file, |name| env::var_os(name).is_some())
+    }
+
+    // The layers are applied from the weakest to the strongest, so a later one replaces what an earlier one set:
+    // first the config file, then the environment variables and last the command line.
+    // The options that can be given many times, like --exclude, add up instead.
+    fn layered(
+        mut args: impl Iterator<Item = String>,
+        config_file: impl FnOnce() -> Result<Option<(PathBuf, String)>, ConfigError>,
+        env_var: impl Fn(&str) -> bool,
+    ) -> Result<Config, ConfigError> {
+        //if args.len() < 3 {
+        //    return Err("Not enough arguments!");
+        //}
+        // We need to clone the values because we are borrowing them from the args vector,
+        // if we don't clone them, the ownership of the values will be moved to the Config struct
+        // which is a violation of the borrowing rules.
+        // This operation might be expensive if the values are large.
+        //let query = args[1].clone();
+        //let file_path = args[2].clone();
+
+        // After refactoring using an iterator.
+        args.next(); // Skip the first argument which is the program name.
+        let mut args = args.peekable();
+
+        // Like git, a subcommand has to come first, so a query can still be "index" when it comes later or after -e.
+        let mut config = Config {
+            index: args.next_if(|arg| arg == "index").is_some(),
+            ..Config::default()
+        };
+        let (options, positional) = parse_options(args)?;
+
+        // --help and --version work even when the config file is broken.
+        for (name, _) in &options {
+            match name.as_str() {
+                "help" => return Err(ConfigError::Help),
+                "version" => return Err(ConfigError::Version),
+                _ => {}
+            }
+        }
+        let no_config = options.iter().any(|(name, _)| name == "no-config");
+        if let Some((path, contents)) = if no_config { None } else { config_file()? } {
+            config.apply_config_file(&path, &contents)?;
+            config.config_file = Some(path);
+        }
+        // The env::var function returns a Result type, if the variable is not found it returns an Err value.
+        if env_var("IGNORE_CASE") {
+            config.set("ignore-case", None, Source::Env("IGNORE_CASE"))?;
+        }
+        for (name, value) in options {
+            config.set(&name, value, Source::CommandLine)?;
+        }
+
+        // minigrep index doesn't have a query, every positional argument is a directory to index.
+        if config.index {
+            config.paths = positional;
+            if config.paths.is_empty() {
+                config.paths.push(String::from("."));
+            }
+            return Ok(config);
+        }
+
+        let mut args = positional.into_iter();
+
+        // With -e or -f the patterns are already known, so every positional argument is a path.
+        if config.patterns.is_empty() && config.pattern_files.is_empty() {
+            config.query = match args.next() {
+                Some(arg) => arg,
+                None => return Err(ConfigError::MissingQuery),
+            };
+        }
+
+        // Every argument after the query is a path to search, run reads stdin when there are none.
+        config.paths = args.collect();
+
+        if config.write && config.replace.is_none() {
+            return Err(ConfigError::NeedsOption {
+                option: String::from("--write"),
+                needed: String::from("--replace"),
+            });
+        }
+        if config.watch {
+            if config.paths.is_empty() {
+                return Err(ConfigError::NeedsOption {
+                    option: String::from("--watch"),
+                    needed: String::from("PATH"),
+                });
+            }
+            // The new lines are searched a few at a time, so the counts and the context would only be about those.
+            let other = match config.mode {
+                OutputMode::Count => Some("--count"),
+                OutputMode::FilesWithMatches => Some("--files-with-matches"),
+                _ if config.before_context > 0 || config.after_context > 0 => Some("--context"),
+                _ if config.replace.is_some() => Some("--replace"),
+                _ => None,
+            };
+            if let Some(other) = other {
+                return Err(ConfigError::Conflict {
+                    option: String::from("--watch"),
+                    other: String::from(other),
+                });
+            }
+        }
+        // The fuzzy lines are ranked by distance, so there is no order for the context lines to follow.
+        if config.fuzzy.is_some() {
+            let other = if config.regex {
+                Some("--regex")
+            } else if config.before_context > 0 || config.after_context > 0 {
+                Some("--context")
+            } else {
+                None
+            };
+            if let Some(other) = other {
+                return Err(ConfigError::Conflict {
+                    option: String::from("--fuzzy"),
+                    other: String::from(other),
+                });
+            }
+        }
+
+        Ok(config)
+    }
+
+    // Every line of the file is one option like on the command line, with its value after an '=': --context=2
+    // Empty lines and the lines that start with '#' are skipped.
+    fn apply_config_file(&mut self, path: &Path, contents: &str) -> Result<(), ConfigError> {
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| ConfigError::ConfigFile {
+                path: path.display().to_string(),
+                line: Some(i + 1),
+                message,
+            };
+            let (options, positional) =
+                parse_options(iter::once(line.to_string())).map_err(|e| error(e.to_string()))?;
+            if !positional.is_empty() {
+                return Err(error(format!("'{line}' isn't an option!")));
+            }
+            for (name, value) in options {
+                if !allowed_in_config_file(&name) {
+                    return Err(error(format!(
+                        "Option '--{name}' can't be used in a config file!"
+                    )));
+                }
+                let source = Source::File {
+                    path: path.to_path_buf(),
+                    line: i + 1,
+                };
+                self.set(&name, value, source)
+                    .map_err(|e| error(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns every pattern to search for: the ones from -e, then the lines of the -f files,
+    /// or only the query when neither option was used.
+    pub fn queries(&self) -> Result<Vec<String>, MinigrepError> {
+        if self.patterns.is_empty() && self.pattern_files.is_empty() {
+            return Ok(vec![self.query.clone()]);
+        }
+        let mut queries = self.patterns.clone();
+        for file in &self.pattern_files {
+            // The error from read_to_string doesn't say which file failed, so we add it.
+            let contents = fs::read_to_string(file).map_err(|error| MinigrepError::Io {
+                path: file.clone(),
+                error,
+            })?;
+            queries.extend(contents.lines().map(String::from));
+        }
+        Ok(queries)
+    }
+
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            ignore_case: self.ignore_case,
+            regex: self.regex,
+            invert_match: self.invert_match,
+            word_regexp: self.word_regexp,
+            line_regexp: self.line_regexp,
+            fuzzy: self.fuzzy,
+        }
+    }
+
+    /// The filter for the files found inside directories.
+    pub fn filter(&self) -> Filter {
+        Filter::new(&self.include, &self.exclude, !self.no_ignore)
+    }
+
+    pub fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            before: self.before_context,
+            after: self.after_context,
+            text: self.text,
+            lossy: self.lossy,
+        }
+    }
+
+    fn set(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+        source: Source,
+    ) -> Result<(), ConfigError> {
+        let option = format!("--{name}");
+        let shown = value.clone();
+        // takes_value already made sure that value is Some for the options that need it.
+        match (name, value) {
+            ("regexp", Some(value)) => self.patterns.push(value),
+            ("file", Some(value)) => self.pattern_files.push(value),
+            ("ignore-case", None) => self.ignore_case = true,
+            ("no-ignore-case", None) => self.ignore_case = false,
+            ("regex", None) => self.regex = true,
+            ("invert-match", None) => self.invert_match = true,
+            ("word-regexp", None) => self.word_regexp = true,
+            ("line-regexp", None) => self.line_regexp = true,
+            ("line-number", None) => self.line_number = true,
+            ("count", None) => self.mode = OutputMode::Count,
+            ("files-with-matches", None) => self.mode = OutputMode::FilesWithMatches,
+            ("only-matching", None) => self.mode = OutputMode::OnlyMatching,
+            ("json", None) => self.mode = OutputMode::Json,
+            ("after-context", Some(value)) => {
+                self.after_context = number(&option, value, MAX_CONTEXT)?
+            }
+            ("before-context", Some(value)) => {
+                self.before_context = number(&option, value, MAX_CONTEXT)?
+            }
+            ("context", Some(value)) => {
+                self.after_context = number(&option, value, MAX_CONTEXT)?;
+                self.before_context = self.after_context;
+            }
+            ("replace", Some(value)) => self.replace = Some(value),
+            ("write", None) => self.write = true,
+            ("include", Some(value)) => self.include.push(glob(&option, value)?),
+            ("exclude", Some(value)) => self.exclude.push(glob(&option, value)?),
+            ("no-ignore", None) => self.no_ignore = true,
+            ("text", None) => self.text = true,
+            ("lossy", None) => self.lossy = true,
+            ("jobs", Some(value)) => {
+                self.jobs = match number(&option, value.clone(), MAX_JOBS)? {
+                    0 => return Err(ConfigError::InvalidValue { option, value }),
+                    jobs => Some(jobs),
+                }
+            }
+            ("watch", None) => self.watch = true,
+            ("fuzzy", Some(value)) => self.fuzzy = Some(number(&option, value, usize::MAX)?),
+            ("color" | "colour", value) => {
+                self.color = match value.as_deref() {
+                    None | Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    Some(_) => {
+                        return Err(ConfigError::InvalidValue {
+                            option,
+                            value: value.unwrap(),
+                        })
+                    }
+                }
+            }
+            ("debug-config", None) => self.debug_config = true,
+            ("no-config", None) => {} // Build already skipped the config file.
+            ("help", None) => return Err(ConfigError::Help),
+            ("version", None) => return Err(ConfigError::Version),
+            _ => return Err(ConfigError::UnknownOption(option)),
+        }
+        self.record(name, shown, source);
+        Ok(())
+    }
+
+    // Remembers which layer set the option for --debug-config. Like the option itself, a later layer replaces
+    // what an earlier one set, except for the options that can be given many times.
+    fn record(&mut self, name: &str, value: Option<String>, source: Source) {
+        let value = value.unwrap_or_else(|| String::from("true"));
+        let settings = match name {
+            "no-ignore-case" => vec![("ignore-case", String::from("false"))],
+            "count" | "files-with-matches" | "only-matching" | "json" => {
+                vec![("output", name.to_string())]
+            }
+            "context" => vec![("before-context", value.clone()), ("after-context", value)],
+            "color" | "colour" if value == "true" => vec![("color", String::from("auto"))],
+            "colour" => vec![("color", value)],
+            "debug-config" | "no-config" => vec![],
+            _ => vec![(name, value)],
+        };
+        for (setting, value) in settings {
+            if !matches!(setting, "regexp" | "file" | "include" | "exclude") {
+                self.origins.retain(|origin| origin.setting != setting);
+            }
+            self.origins.push(Origin {
+                setting: setting.to_string(),
+                value,
+                source: source.clone(),
+            });
+        }
+    }
+
+    /// What --debug-config prints: the config file that was read, and every option that was set with the layer that set it.
+    pub fn debug_report(&self) -> String {
+        let mut report = match &self.config_file {
+            Some(path) => format!("config file: {}\n", path.display()),
+            None => {
+                format!("config file: none ({CONFIG_FILE} wasn't found, or --no-config was used)\n")
+            }
+        };
+        for origin in &self.origins {
+            report += &format!("{}: {} ({})\n", origin.setting, origin.value, origin.source);
+        }
+        report += "every other option has its default value\n";
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Without a config file or environment variables, so the tests don't depend on where they run.
+    fn build(args: &[&str]) -> Result<Config, ConfigError> {
+        layered(args, None, false)
+    }
+
+    fn layered(
+        args: &[&str],
+        config_file: Option<&str>,
+        ignore_case_env: bool,
+    ) -> Result<Config, ConfigError> {
+        let args = std::iter::once("minigrep").chain(args.iter().copied());
+        let config_file =
+            config_file.map(|contents| (PathBuf::from(CONFIG_FILE), contents.to_string()));
+        Config::layered(
+            args.map(String::from),
+            || Ok(config_file),
+            |name| ignore_case_env && name == "IGNORE_CASE",
+        )
+    }
+
+    #[test]
+    fn layers() {
+        let file = "# Defaults for this project\n--ignore-case\n\n--context=2\n--exclude=target/**\n--color=always\n";
+        let config = layered(&["-A1", "--exclude=*.log", "query"], Some(file), false).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!((config.before_context, config.after_context), (2, 1));
+        assert_eq!(config.exclude.len(), 2);
+        assert_eq!(config.color, ColorChoice::Always);
+        let origin = |setting: &str| {
+            config
+                .origins
+                .iter()
+                .filter(|origin| origin.setting == setting)
+                .map(|origin| (origin.value.as_str(), origin.source.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            origin("ignore-case"),
+            vec![("true", String::from(".minigreprc line 2"))]
+        );
+        assert_eq!(
+            origin("before-context"),
+            vec![("2", String::from(".minigreprc line 4"))]
+        );
+        assert_eq!(
+            origin("after-context"),
+            vec![("1", String::from("command line"))]
+        );
+        assert_eq!(
+            origin("exclude"),
+            vec![
+                ("target/**", String::from(".minigreprc line 5")),
+                ("*.log", String::from("command line"))
+            ]
+        );
+
+        // The environment wins over the file, and the command line over both.
+        let config = layered(&["query"], Some("--no-ignore-case"), true).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!(config.origins[0].source, Source::Env("IGNORE_CASE"));
+        assert!(
+            !layered(&["-i", "--no-ignore-case", "query"], None, true)
+                .unwrap()
+                .ignore_case
+        );
+        assert!(
+            !layered(&["--no-config", "query"], Some("-i"), false)
+                .unwrap()
+                .ignore_case
+        );
+    }
+
+    #[test]
+    fn config_file_errors() {
+        let error = |file: &str| {
+            layered(&["query"], Some(file), false)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("-n\n--context"),
+            ".minigreprc line 2: Option '--context' needs a value!"
+        );
+        assert_eq!(
+            error("--replace=x"),
+            ".minigreprc line 1: Option '--replace' can't be used in a config file!"
+        );
+        assert_eq!(
+            error("--context 2"),
+            ".minigreprc line 1: Unknown option '--context 2'!"
+        );
+        assert_eq!(
+            error("query"),
+            ".minigreprc line 1: 'query' isn't an option!"
+        );
+        // A broken file doesn't stop --help.
+        assert_eq!(
+            layered(&["--help"], Some("--bogus"), false).unwrap_err(),
+            ConfigError::Help
+        );
+    }
+
+    #[test]
+    fn combined_short_flags_and_values() {
+        let config = build(&["-inA2", "query", "a.txt", "b.txt"]).unwrap();
+        assert!(config.ignore_case);
+        assert!(config.line_number);
+        assert_eq!(config.after_context, 2);
+        assert_eq!(config.paths, vec!["a.txt", "b.txt"]);
+
+        let config = build(&["query", "-B", "3", "--context=1", "--count", "a.txt"]).unwrap();
+        assert_eq!((config.before_context, config.after_context), (1, 1));
+        assert_eq!(config.mode, OutputMode::Count);
+    }
+
+    #[test]
+    fn patterns_make_every_positional_a_path() {
+        let config = build(&["-e", "foo", "--regexp=bar", "-fwords.txt", "a.txt"]).unwrap();
+        assert_eq!(config.patterns, vec!["foo", "bar"]);
+        assert_eq!(config.pattern_files, vec!["words.txt"]);
+        assert!(config.query.is_empty());
+        assert_eq!(config.paths, vec!["a.txt"]);
+        assert_eq!(
+            build(&["-e", "foo"]).unwrap().queries().unwrap(),
+            vec!["foo"]
+        );
+    }
+
+    #[test]
+    fn index_subcommand() {
+        let config = build(&["index", "notes", "logs"]).unwrap();
+        assert!(config.index);
+        assert_eq!(config.paths, vec!["notes", "logs"]);
+        assert_eq!(build(&["index"]).unwrap().paths, vec!["."]);
+        // Anywhere else "index" is a query.
+        let config = build(&["-n", "index", "notes"]).unwrap();
+        assert!(!config.index);
+        assert_eq!(config.query, "index");
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = build(&["--no-ignore-case", "--", "-n", "--file.txt"]).unwrap();
+        assert!(!config.ignore_case);
+        assert!(!config.line_number);
+        assert_eq!(config.query, "-n");
+        assert_eq!(config.paths, vec!["--file.txt"]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(build(&[]).unwrap_err(), ConfigError::MissingQuery);
+        assert!(build(&["query"]).unwrap().paths.is_empty());
+        assert_eq!(
+            build(&["-q", "query", "a.txt"]).unwrap_err(),
+            ConfigError::UnknownOption(String::from("-q"))
+        );
+        assert_eq!(
+            build(&["query", "a.txt", "-A"]).unwrap_err(),
+            ConfigError::MissingValue(String::from("-A"))
+        );
+        assert_eq!(
+            build(&["--regex=yes", "query", "a.txt"]).unwrap_err(),
+            ConfigError::UnexpectedValue(String::from("--regex"))
+        );
+        assert_eq!(
+            build(&["--context", "two", "query", "a.txt"]).unwrap_err(),
+            ConfigError::InvalidValue {
+                option: String::from("--context"),
+                value: String::from("two")
+            }
+        );
+        assert_eq!(
+            build(&["-B", "18446744073709551615", "query"]).unwrap_err(),
+            ConfigError::InvalidValue {
+                option: String::from("--before-context"),
+                value: String::from("18446744073709551615")
+            }
+        );
+        assert_eq!(
+            build(&["--color=sometimes", "query"]).unwrap_err(),
+            ConfigError::InvalidValue {
+                option: String::from("--color"),
+                value: String::from("sometimes")
+            }
+        );
+        assert_eq!(
+            build(&["-j0", "query"]).unwrap_err(),
+            ConfigError::InvalidValue {
+                option: String::from("--jobs"),
+                value: String::from("0")
+            }
+        );
+        assert_eq!(
+            build(&["-j", "200000", "query"]).unwrap_err(),
+            ConfigError::InvalidValue {
+                option: String::from("--jobs"),
+                value: String::from("200000")
+            }
+        );
+        assert_eq!(
+            build(&["--include", "[a-", "query"]).unwrap_err(),
+            ConfigError::InvalidValue {
+                option: String::from("--include"),
+                value: String::from("[a-")
+            }
+        );
+        assert_eq!(
+            build(&["--write", "query"]).unwrap_err(),
+            ConfigError::NeedsOption {
+                option: String::from("--write"),
+                needed: String::from("--replace")
+            }
+        );
+        assert_eq!(
+            build(&["--fuzzy", "2", "-E", "query"]).unwrap_err(),
+            ConfigError::Conflict {
+                option: String::from("--fuzzy"),
+                other: String::from("--regex")
+            }
+        );
+        assert_eq!(
+            build(&["--watch", "query"]).unwrap_err(),
+            ConfigError::NeedsOption {
+                option: String::from("--watch"),
+                needed: String::from("PATH")
+            }
+        );
+        assert_eq!(
+            build(&["--watch", "-c", "query", "app.log"]).unwrap_err(),
+            ConfigError::Conflict {
+                option: String::from("--watch"),
+                other: String::from("--count")
+            }
+        );
+        assert_eq!(build(&["-h"]).unwrap_err(), ConfigError::Help);
+        assert_eq!(build(&["--version"]).unwrap_err(), ConfigError::Version);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{lines_with_offsets, Match};
+
+/// A line inside a group, either a line that matched or a line around it.
+#[derive(Debug, PartialEq)]
+pub enum Line<'a> {
+    Match(Match<'a>),
+    Context {
+        line_number: usize,
+        offset: usize,
+        line: &'a str,
+    },
+}
+
+/// Matches that are close to each other together with their context lines.
+/// Groups are printed separated by "--" like grep does.
+#[derive(Debug, PartialEq)]
+pub struct Group<'a> {
+    pub lines: Vec<Line<'a>>,
+}
+
+/// An iterator over the groups of matches in the contents.
+pub struct Groups<'a> {
+    lines: Vec<(usize, &'a str)>,
+    matches: std::iter::Peekable<std::vec::IntoIter<Match<'a>>>,
+    before: usize,
+    after: usize,
+}
+
+/// Groups the matches with `before` lines of context before them and `after` lines after them.
+///
+/// The matches must come from `search_matches` on the same contents.
+/// When the context of two matches overlaps or touches, they end up in the same group.
+pub fn groups<'a>(
+    contents: &'a str,
+    matches: Vec<Match<'a>>,
+    before: usize,
+    after: usize,
+) -> Groups<'a> {
+    Groups {
+        lines: lines_with_offsets(contents).collect(),
+        matches: matches.into_iter().peekable(),
+        before,
+        after,
+    }
+}
+
+impl<'a> Iterator for Groups<'a> {
+    type Item = Group<'a>;
+
+    fn next(&mut self) -> Option<Group<'a>> {
+        let first = self.matches.next()?;
+        // Line numbers start at 1 but the indexes of the lines vector start at 0.
+        let start = (first.line_number - 1).saturating_sub(self.before);
+        let mut end = (first.line_number - 1).saturating_add(self.after);
+        let mut matches = vec![first];
+
+        // Keep taking matches while their context would start before the current group ends.
+        while let Some(next) = self.matches.peek() {
+            if (next.line_number - 1).saturating_sub(self.before) > end.saturating_add(1) {
+                break;
+            }
+            end = (next.line_number - 1).saturating_add(self.after);
+            matches.push(self.matches.next().unwrap());
+        }
+        let end = end.min(self.lines.len() - 1);
+
+        let mut matches = matches.into_iter().peekable();
+        let mut lines = Vec::new();
+        for index in start..=end {
+            let (offset, line) = self.lines[index];
+            match matches.next_if(|m| m.line_number == index + 1) {
+                Some(m) => lines.push(Line::Match(m)),
+                None => lines.push(Line::Context {
+                    line_number: index + 1,
+                    offset,
+                    line,
+                }),
+            }
+        }
+        Some(Group { lines })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_matches, SearchOptions, Searcher};
+
+    fn line_numbers(group: &Group) -> Vec<(usize, bool)> {
+        group
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Match(m) => (m.line_number, true),
+                Line::Context { line_number, .. } => (*line_number, false),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_overlapping_context() {
+        let contents = "a\nmatch\nb\nc\nmatch\nd\ne\nf\ng\nmatch";
+        let searcher = Searcher::build("match", SearchOptions::default()).unwrap();
+        let matches = search_matches(&searcher, contents);
+        let groups: Vec<Group> = groups(contents, matches, 1, 1).collect();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            line_numbers(&groups[0]),
+            vec![
+                (1, false),
+                (2, true),
+                (3, false),
+                (4, false),
+                (5, true),
+                (6, false)
+            ]
+        );
+        // The last match is at the end of the contents, so there are no lines after it.
+        assert_eq!(line_numbers(&groups[1]), vec![(9, false), (10, true)]);
+    }
+
+    #[test]
+    fn touching_context_is_one_group() {
+        let contents = "match\na\nb\nmatch";
+        let searcher = Searcher::build("match", SearchOptions::default()).unwrap();
+        let matches = search_matches(&searcher, contents);
+        assert_eq!(groups(contents, matches, 1, 1).count(), 1);
+    }
+
+    #[test]
+    fn huge_context_doesnt_overflow() {
+        let contents = "match\na\nmatch";
+        let searcher = Searcher::build("match", SearchOptions::default()).unwrap();
+        let matches = search_matches(&searcher, contents);
+        let groups: Vec<Group> = groups(contents, matches, usize::MAX, usize::MAX).collect();
+        assert_eq!(
+            line_numbers(&groups[0]),
+            vec![(1, true), (2, false), (3, true)]
+        );
+    }
+}
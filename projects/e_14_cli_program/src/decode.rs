@@ -0,0 +1,185 @@
+use std::io::{self, BufRead, Read};
+
+// With --lossy, files that aren't UTF-8 are turned into UTF-8 before they are searched:
+// - A file that starts with a UTF-16 byte order mark is decoded as UTF-16 by Utf16Reader.
+// - The byte order mark of a UTF-8 file is skipped.
+// - Any other line that isn't valid UTF-8 is decoded as Latin-1, where every byte is one character.
+//   Latin-1 can decode any byte, so it is only a guess, but it is right for old Western European text.
+
+/// The byte order mark (BOM) at the start of a file, the character U+FEFF encoded in the file's encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Bom {
+    pub fn detect(bytes: &[u8]) -> Option<Bom> {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            Some(Bom::Utf8)
+        } else if bytes.starts_with(&[0xFF, 0xFE]) {
+            Some(Bom::Utf16Le)
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            Some(Bom::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// The size of the mark in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Bom::Utf8 => 3,
+            Bom::Utf16Le | Bom::Utf16Be => 2,
+        }
+    }
+}
+
+/// Decodes the bytes as Latin-1 (ISO-8859-1), the first 256 Unicode characters have the same numbers as its bytes.
+pub fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+/// Reads UTF-16 from `inner` and returns it as UTF-8, so it can be searched line by line like any other file.
+/// Code units that aren't valid UTF-16, like a surrogate without its pair, become U+FFFD.
+pub struct Utf16Reader<R> {
+    inner: R,
+    big_endian: bool,
+    decoded: Vec<u8>,
+    position: usize,             // How much of decoded was already consumed.
+    odd_byte: Option<u8>,        // The first half of a code unit that was split between two reads.
+    high_surrogate: Option<u16>, // The first half of a surrogate pair, waiting for the second one.
+}
+
+impl<R: BufRead> Utf16Reader<R> {
+    /// The byte order mark must already be skipped.
+    pub fn new(inner: R, big_endian: bool) -> Utf16Reader<R> {
+        Utf16Reader {
+            inner,
+            big_endian,
+            decoded: Vec::new(),
+            position: 0,
+            odd_byte: None,
+            high_surrogate: None,
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut encoded = [0; 4];
+        self.decoded
+            .extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+    }
+
+    fn push_unit(&mut self, unit: u16) {
+        if let Some(high) = self.high_surrogate.take() {
+            if let 0xDC00..=0xDFFF = unit {
+                let code =
+                    0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(unit) - 0xDC00);
+                self.push_char(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                return;
+            }
+            self.push_char(char::REPLACEMENT_CHARACTER);
+        }
+        match unit {
+            0xD800..=0xDBFF => self.high_surrogate = Some(unit),
+            _ => self
+                .push_char(char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER)),
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for Utf16Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A read can decode to nothing (one byte, or a high surrogate alone), so we keep reading until there is something.
+        while self.position == self.decoded.len() {
+            self.decoded.clear();
+            self.position = 0;
+            let bytes = self.inner.fill_buf()?.to_vec();
+            if bytes.is_empty() {
+                // The input ended in the middle of a character.
+                if self.odd_byte.take().is_some() || self.high_surrogate.take().is_some() {
+                    self.push_char(char::REPLACEMENT_CHARACTER);
+                    continue;
+                }
+                break;
+            }
+            self.inner.consume(bytes.len());
+            for byte in bytes {
+                match self.odd_byte.take() {
+                    None => self.odd_byte = Some(byte),
+                    Some(first) => {
+                        let unit = if self.big_endian {
+                            u16::from_be_bytes([first, byte])
+                        } else {
+                            u16::from_le_bytes([first, byte])
+                        };
+                        self.push_unit(unit);
+                    }
+                }
+            }
+        }
+        Ok(&self.decoded[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.decoded.len());
+    }
+}
+
+impl<R: BufRead> Read for Utf16Reader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(out.len());
+        out[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_utf16() {
+        let text = "grüße\n🦀 crab\n";
+        for big_endian in [false, true] {
+            let bytes = utf16(text, big_endian);
+            // A BufReader with a tiny buffer splits the code units and the surrogate pair of the crab between reads.
+            let inner = io::BufReader::with_capacity(3, bytes.as_slice());
+            let mut decoded = String::new();
+            Utf16Reader::new(inner, big_endian)
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, text);
+        }
+        // A low surrogate alone and a half code unit at the end.
+        let mut decoded = String::new();
+        let bytes = [0x61, 0x00, 0x00, 0xDC, 0x62];
+        Utf16Reader::new(&bytes[..], false)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "a\u{FFFD}\u{FFFD}");
+    }
+
+    #[test]
+    fn bom_and_latin1() {
+        assert_eq!(Bom::detect(b"\xEF\xBB\xBFtext"), Some(Bom::Utf8));
+        assert_eq!(Bom::detect(b"\xFF\xFEt\0"), Some(Bom::Utf16Le));
+        assert_eq!(Bom::detect(b"text"), None);
+        assert_eq!(latin1(b"caf\xE9"), "café");
+    }
+}
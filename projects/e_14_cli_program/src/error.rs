@@ -0,0 +1,159 @@
+use std::{cell::Cell, error::Error, fmt, io};
+
+use crate::{regex::RegexError, walk::WalkError, ConfigError};
+
+// Like grep, main exits with 0 when a line was selected, 1 when none was and 2 after an error,
+// so a script can tell "nothing found" from "something broke".
+
+/// Everything that can stop minigrep.
+#[derive(Debug)]
+pub enum MinigrepError {
+    Config(ConfigError),
+    Pattern(RegexError), // A --regex pattern that doesn't compile.
+    // A path that couldn't be read or written: a file or directory to search, a -f file, an index.
+    Io { path: String, error: io::Error },
+    Output(io::Error), // The results couldn't be written, like when stdout is a closed pipe.
+    Thread(io::Error), // The system refused to start another thread for -j.
+}
+
+impl MinigrepError {
+    pub fn exit_code(&self) -> i32 {
+        // Something was being printed, so a line was selected. See is_broken_pipe.
+        if self.is_broken_pipe() {
+            return 0;
+        }
+        2
+    }
+
+    /// The reader of the output went away, like `head -1` after its line.
+    ///
+    /// grep is killed by SIGPIPE then, without a message. Rust ignores that signal, so the write fails
+    /// instead and main exits quietly.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, MinigrepError::Output(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinigrepError::Config(e) => write!(f, "{e}"),
+            MinigrepError::Pattern(e) => write!(f, "{e}"),
+            MinigrepError::Io { path, error } => write!(f, "{path}: {error}"),
+            MinigrepError::Output(e) => write!(f, "can't write the results: {e}"),
+            MinigrepError::Thread(e) => write!(f, "can't start a search thread: {e}"),
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Config(e) => Some(e),
+            MinigrepError::Pattern(e) => Some(e),
+            MinigrepError::Io { error, .. } => Some(error),
+            MinigrepError::Output(e) => Some(e),
+            MinigrepError::Thread(e) => Some(e),
+        }
+    }
+}
+
+impl From<ConfigError> for MinigrepError {
+    fn from(e: ConfigError) -> MinigrepError {
+        MinigrepError::Config(e)
+    }
+}
+
+impl From<RegexError> for MinigrepError {
+    fn from(e: RegexError) -> MinigrepError {
+        MinigrepError::Pattern(e)
+    }
+}
+
+impl From<WalkError> for MinigrepError {
+    fn from(e: WalkError) -> MinigrepError {
+        MinigrepError::Io {
+            path: e.path.display().to_string(),
+            error: e.error,
+        }
+    }
+}
+
+/// What run found when it got to the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Match,
+    NoMatch,
+    // Some paths couldn't be searched, their errors were printed as warnings. Like grep this is an error
+    // even when other paths matched, because the missing ones could have changed the answer.
+    PathErrors,
+}
+
+impl Outcome {
+    pub(crate) fn new(matched: bool, warnings: &Warnings) -> Outcome {
+        match (warnings.any(), matched) {
+            (true, _) => Outcome::PathErrors,
+            (false, true) => Outcome::Match,
+            (false, false) => Outcome::NoMatch,
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Match => 0,
+            Outcome::NoMatch => 1,
+            Outcome::PathErrors => 2,
+        }
+    }
+}
+
+// The errors that only stop one path are printed and the search goes on, this remembers that there were some.
+// Only the thread that walks the paths and prints reports them, so a Cell is enough.
+#[derive(Debug, Default)]
+pub(crate) struct Warnings {
+    count: Cell<usize>,
+}
+
+impl Warnings {
+    pub(crate) fn report(&self, error: MinigrepError) {
+        eprintln!("minigrep: {error}");
+        self.count.set(self.count.get() + 1);
+    }
+
+    pub(crate) fn any(&self) -> bool {
+        self.count.get() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let warnings = Warnings::default();
+        assert_eq!(Outcome::new(true, &warnings).exit_code(), 0);
+        assert_eq!(Outcome::new(false, &warnings).exit_code(), 1);
+        warnings.report(MinigrepError::Io {
+            path: String::from("missing.txt"),
+            error: io::Error::from(io::ErrorKind::NotFound),
+        });
+        assert_eq!(Outcome::new(true, &warnings), Outcome::PathErrors);
+        assert_eq!(Outcome::new(true, &warnings).exit_code(), 2);
+
+        let error = MinigrepError::from(ConfigError::MissingQuery);
+        assert_eq!(error.to_string(), "Query not found!");
+        assert_eq!(error.exit_code(), 2);
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn broken_pipe_is_quiet() {
+        let error = MinigrepError::Output(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(error.is_broken_pipe());
+        assert_eq!(error.exit_code(), 0);
+        let error = MinigrepError::Output(io::Error::from(io::ErrorKind::StorageFull));
+        assert!(!error.is_broken_pipe());
+        assert_eq!(error.exit_code(), 2);
+    }
+}
@@ -0,0 +1,225 @@
+// Case folding is what Unicode recommends for case-insensitive comparisons.
+// For most characters it is the same as to_lowercase, but some characters fold to something else
+// or to more than one character, like 'ß' which folds to "ss" so that "STRASSE" matches "straße".
+// The table below has the entries of Unicode's CaseFolding.txt (statuses C and F) that are
+// different from char::to_lowercase, every other character falls back to to_lowercase.
+// It uses the default folding, not the Turkic one, so 'I' folds to 'i' and 'İ' folds to "i̇".
+
+fn special_fold(c: char) -> Option<&'static str> {
+    // The results are written with escapes because many of them use combining characters
+    // that look exactly like the precomposed character they come from.
+    Some(match c {
+        '\u{B5}' => "\u{3BC}",                             // µ
+        '\u{DF}' | '\u{1E9E}' => "ss",                     // ß ẞ
+        '\u{149}' => "\u{2BC}n",                           // ŉ
+        '\u{17F}' => "s",                                  // ſ
+        '\u{1F0}' => "j\u{30C}",                           // ǰ
+        '\u{345}' | '\u{1FBE}' => "\u{3B9}",               // Iota subscripts
+        '\u{390}' | '\u{1FD3}' => "\u{3B9}\u{308}\u{301}", // ΐ
+        '\u{3B0}' | '\u{1FE3}' => "\u{3C5}\u{308}\u{301}", // ΰ
+        '\u{3C2}' => "\u{3C3}",                            // ς
+        '\u{3D0}' => "\u{3B2}",                            // ϐ
+        '\u{3D1}' => "\u{3B8}",                            // ϑ
+        '\u{3D5}' => "\u{3C6}",                            // ϕ
+        '\u{3D6}' => "\u{3C0}",                            // ϖ
+        '\u{3F0}' => "\u{3BA}",                            // ϰ
+        '\u{3F1}' => "\u{3C1}",                            // ϱ
+        '\u{3F5}' => "\u{3B5}",                            // ϵ
+        '\u{587}' => "\u{565}\u{582}",                     // և
+        '\u{1C80}' => "\u{432}", // Old Cyrillic variants of в, д, о, с, т, ъ, ѣ and ꙋ
+        '\u{1C81}' => "\u{434}",
+        '\u{1C82}' => "\u{43E}",
+        '\u{1C83}' => "\u{441}",
+        '\u{1C84}' | '\u{1C85}' => "\u{442}",
+        '\u{1C86}' => "\u{44A}",
+        '\u{1C87}' => "\u{463}",
+        '\u{1C88}' => "\u{A64B}",
+        '\u{1E96}' => "h\u{331}",                    // ẖ
+        '\u{1E97}' => "t\u{308}",                    // ẗ
+        '\u{1E98}' => "w\u{30A}",                    // ẘ
+        '\u{1E99}' => "y\u{30A}",                    // ẙ
+        '\u{1E9A}' => "a\u{2BE}",                    // ẚ
+        '\u{1E9B}' => "\u{1E61}",                    // ẛ
+        '\u{1F50}' => "\u{3C5}\u{313}",              // ὐ
+        '\u{1F52}' => "\u{3C5}\u{313}\u{300}",       // ὒ
+        '\u{1F54}' => "\u{3C5}\u{313}\u{301}",       // ὔ
+        '\u{1F56}' => "\u{3C5}\u{313}\u{342}",       // ὖ
+        '\u{1FB2}' => "\u{1F70}\u{3B9}",             // ᾲ
+        '\u{1FB3}' | '\u{1FBC}' => "\u{3B1}\u{3B9}", // ᾳ ᾼ
+        '\u{1FB4}' => "\u{3AC}\u{3B9}",              // ᾴ
+        '\u{1FB6}' => "\u{3B1}\u{342}",              // ᾶ
+        '\u{1FB7}' => "\u{3B1}\u{342}\u{3B9}",       // ᾷ
+        '\u{1FC2}' => "\u{1F74}\u{3B9}",             // ῂ
+        '\u{1FC3}' | '\u{1FCC}' => "\u{3B7}\u{3B9}", // ῃ ῌ
+        '\u{1FC4}' => "\u{3AE}\u{3B9}",              // ῄ
+        '\u{1FC6}' => "\u{3B7}\u{342}",              // ῆ
+        '\u{1FC7}' => "\u{3B7}\u{342}\u{3B9}",       // ῇ
+        '\u{1FD2}' => "\u{3B9}\u{308}\u{300}",       // ῒ
+        '\u{1FD6}' => "\u{3B9}\u{342}",              // ῖ
+        '\u{1FD7}' => "\u{3B9}\u{308}\u{342}",       // ῗ
+        '\u{1FE2}' => "\u{3C5}\u{308}\u{300}",       // ῢ
+        '\u{1FE4}' => "\u{3C1}\u{313}",              // ῤ
+        '\u{1FE6}' => "\u{3C5}\u{342}",              // ῦ
+        '\u{1FE7}' => "\u{3C5}\u{308}\u{342}",       // ῧ
+        '\u{1FF2}' => "\u{1F7C}\u{3B9}",             // ῲ
+        '\u{1FF3}' | '\u{1FFC}' => "\u{3C9}\u{3B9}", // ῳ ῼ
+        '\u{1FF4}' => "\u{3CE}\u{3B9}",              // ῴ
+        '\u{1FF6}' => "\u{3C9}\u{342}",              // ῶ
+        '\u{1FF7}' => "\u{3C9}\u{342}\u{3B9}",       // ῷ
+        '\u{FB00}' => "ff",                          // Latin ligatures
+        '\u{FB01}' => "fi",
+        '\u{FB02}' => "fl",
+        '\u{FB03}' => "ffi",
+        '\u{FB04}' => "ffl",
+        '\u{FB05}' | '\u{FB06}' => "st",
+        '\u{FB13}' => "\u{574}\u{576}", // Armenian ligatures
+        '\u{FB14}' => "\u{574}\u{565}",
+        '\u{FB15}' => "\u{574}\u{56B}",
+        '\u{FB16}' => "\u{57E}\u{576}",
+        '\u{FB17}' => "\u{574}\u{56D}",
+        _ => return None,
+    })
+}
+
+// Greek letters with a ypogegrammeni (the small iota below, like ᾀ or ᾈ) fold to the letter
+// without it followed by a full iota. In Unicode they are laid out in three rows of 16,
+// for alpha, eta and omega, so the base letter can be computed instead of listed.
+fn ypogegrammeni_base(c: char) -> Option<char> {
+    let c = c as u32;
+    if !(0x1F80..=0x1FAF).contains(&c) {
+        return None;
+    }
+    let row = (c - 0x1F80) / 16;
+    let column = (c - 0x1F80) % 8; // The uppercase half of each row folds like the lowercase half.
+    let base = [0x1F00, 0x1F20, 0x1F60][row as usize] + column;
+    char::from_u32(base)
+}
+
+/// Calls `f` with every character that `c` folds to.
+pub fn fold_char(c: char, mut f: impl FnMut(char)) {
+    if let Some(folded) = special_fold(c) {
+        folded.chars().for_each(f);
+    } else if let Some(base) = ypogegrammeni_base(c) {
+        f(base);
+        f('ι');
+    } else {
+        c.to_lowercase().for_each(f);
+    }
+}
+
+/// Returns the case folded version of the text.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        fold_char(c, |c| folded.push(c));
+    }
+    folded
+}
+
+/// Returns the folded text together with, for every byte of it, the offset in `text` of the character
+/// it came from if the byte is the start of that character's folding, and None for the other bytes.
+pub fn fold_with_offsets(text: &str) -> (String, Vec<Option<usize>>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    for (offset, c) in text.char_indices() {
+        fold_char(c, |c| folded.push(c));
+        origins.push(Some(offset));
+        origins.resize(folded.len(), None);
+    }
+    (folded, origins)
+}
+
+/// Returns true if the two characters are the same after case folding.
+pub fn chars_eq(a: char, b: char) -> bool {
+    if a == b {
+        return true;
+    }
+    let mut folded = Vec::with_capacity(3);
+    fold_char(a, |c| folded.push(c));
+    let mut matches = true;
+    let mut index = 0;
+    fold_char(b, |c| {
+        matches &= folded.get(index) == Some(&c);
+        index += 1;
+    });
+    matches && index == folded.len()
+}
+
+/// Finds every non-overlapping occurrence of `query` in `line`, ignoring case.
+///
+/// `query` must already be folded with `fold`. The returned spans are byte ranges of the original line,
+/// and they always start and end at a whole character, so "s" doesn't match half of 'ß' but "ss" matches all of it.
+pub fn find_folded(query: &str, line: &str) -> Vec<(usize, usize)> {
+    // Every folded character remembers the byte offset of the original character it came from,
+    // and whether it is the first one of that character's folding.
+    let mut folded = Vec::with_capacity(line.len());
+    for (offset, c) in line.char_indices() {
+        let mut first = true;
+        fold_char(c, |c| {
+            folded.push((c, offset, first));
+            first = false;
+        });
+    }
+    let is_boundary = |index: usize| index == folded.len() || folded[index].2;
+    let offset = |index: usize| {
+        folded
+            .get(index)
+            .map_or(line.len(), |&(_, offset, _)| offset)
+    };
+
+    let query: Vec<char> = query.chars().collect();
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start + query.len() <= folded.len() {
+        let end = start + query.len();
+        let found = is_boundary(start)
+            && is_boundary(end)
+            && folded[start..end]
+                .iter()
+                .zip(&query)
+                .all(|(&(c, _, _), &q)| c == q);
+        if found {
+            spans.push((offset(start), offset(end)));
+            // An empty query matches everywhere, we still have to move forward.
+            start = if end > start { end } else { start + 1 };
+        } else {
+            start += 1;
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, line: &str) -> Vec<(usize, usize)> {
+        find_folded(&fold(query), line)
+    }
+
+    #[test]
+    fn sharp_s_and_ligatures() {
+        assert_eq!(find("STRASSE", "die Straße"), vec![(4, 11)]);
+        assert_eq!(find("straße", "DIE STRASSE"), vec![(4, 11)]);
+        // Only half of the 'ß' would match, so it isn't a match.
+        assert_eq!(find("s", "ß"), vec![]);
+        assert_eq!(find("fi", "ﬁne ﬁsh"), vec![(0, 3), (6, 9)]);
+    }
+
+    #[test]
+    fn dotted_i_and_sigma() {
+        // 'İ' (2 bytes) folds to "i̇", an 'i' followed by a combining dot.
+        assert_eq!(find("i̇stanbul", "İSTANBUL"), vec![(0, 9)]);
+        assert_eq!(find("İstanbul", "i̇stanbul"), vec![(0, 10)]);
+        assert_eq!(find("ΟΔΟΣ", "οδος"), vec![(0, 8)]);
+        assert_eq!(find("\u{1F88}", "\u{1F00}\u{3B9}"), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn char_comparison() {
+        assert!(chars_eq('ς', 'Σ'));
+        assert!(chars_eq('ß', 'ẞ'));
+        assert!(chars_eq('K', '\u{212A}')); // The Kelvin sign.
+        assert!(!chars_eq('a', 'b'));
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use crate::fold;
+
+// Approximate matching (--fuzzy): a line matches if some part of it is at most k edits away from the query,
+// where an edit is inserting, deleting or replacing one character (the Levenshtein distance).
+//
+// The distances come from Myers' bit-parallel algorithm ("A fast bit-vector algorithm for approximate
+// string matching based on dynamic programming", 1999). The classic dynamic programming table has one column
+// per character of the line and one row per character of the query, and two cells next to each other differ
+// by -1, 0 or +1. Myers keeps those differences as bits of a u64, so a whole column is computed in a few
+// integer operations instead of one step per row. Queries longer than 64 characters don't fit in one u64,
+// they use the plain table instead, which gives the same results more slowly.
+
+/// A match found by Fuzzy, the byte range in the line and how many edits it is away from the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FuzzyMatch {
+    pub start: usize,
+    pub end: usize,
+    pub distance: usize,
+}
+
+pub struct Fuzzy {
+    query: Vec<char>, // Case folded when ignore_case is on.
+    max_distance: usize,
+    ignore_case: bool,
+    // For every character of the query, the bits of the positions where it appears (only for queries up to 64 characters).
+    peq: HashMap<char, u64>,
+}
+
+// A character of the line, with the byte offset of the character it comes from.
+// Case folding can turn one character into several, only the first of them starts a character of the line.
+#[derive(Clone, Copy)]
+struct Unit {
+    c: char,
+    offset: usize,
+    first: bool,
+}
+
+impl Fuzzy {
+    pub fn build(query: &str, max_distance: usize, ignore_case: bool) -> Fuzzy {
+        let query: Vec<char> = if ignore_case {
+            fold::fold(query).chars().collect()
+        } else {
+            query.chars().collect()
+        };
+        let mut peq = HashMap::new();
+        if query.len() <= 64 {
+            for (i, &c) in query.iter().enumerate() {
+                *peq.entry(c).or_insert(0) |= 1 << i;
+            }
+        }
+        Fuzzy {
+            query,
+            max_distance,
+            ignore_case,
+            peq,
+        }
+    }
+
+    fn units(&self, line: &str) -> Vec<Unit> {
+        let mut units = Vec::with_capacity(line.len());
+        for (offset, c) in line.char_indices() {
+            if self.ignore_case {
+                let mut first = true;
+                fold::fold_char(c, |c| {
+                    units.push(Unit { c, offset, first });
+                    first = false;
+                });
+            } else {
+                units.push(Unit {
+                    c,
+                    offset,
+                    first: true,
+                });
+            }
+        }
+        units
+    }
+
+    // Calls `f` after every character of the text with the smallest distance between the query and
+    // a part of the text that ends there, until `f` returns false.
+    fn scan(&self, text: &[Unit], mut f: impl FnMut(usize, usize) -> bool) {
+        let m = self.query.len();
+        if m > 64 {
+            // column[i] is the distance between the first i characters of the query and the best part of the text
+            // ending at the current character. A part can start anywhere, so row 0 is always 0.
+            let mut column: Vec<usize> = (0..=m).collect();
+            for (j, unit) in text.iter().enumerate() {
+                let mut diagonal = column[0];
+                for i in 1..=m {
+                    let cost = usize::from(self.query[i - 1] != unit.c);
+                    let value = (diagonal + cost).min(column[i] + 1).min(column[i - 1] + 1);
+                    diagonal = column[i];
+                    column[i] = value;
+                }
+                if !f(j + 1, column[m]) {
+                    return;
+                }
+            }
+            return;
+        }
+
+        // Pv and Mv have a bit set for the rows where the column goes up (+1) or down (-1) from the row above.
+        // At the start the column is 0, 1, 2... m, so every row goes up.
+        let mask = if m == 64 { u64::MAX } else { (1 << m) - 1 };
+        let last = 1 << (m - 1);
+        let mut pv = mask;
+        let mut mv = 0;
+        let mut score = m;
+        for (j, unit) in text.iter().enumerate() {
+            let eq = self.peq.get(&unit.c).copied().unwrap_or(0);
+            let xv = eq | mv;
+            let xh = (((eq & pv).wrapping_add(pv)) ^ pv) | eq;
+            // Ph and Mh are the rows where the new column is bigger or smaller than the old one.
+            let ph = mv | !(xh | pv);
+            let mh = pv & xh;
+            if ph & last != 0 {
+                score += 1;
+            } else if mh & last != 0 {
+                score -= 1;
+            }
+            // Row 0 doesn't change from one column to the next, so nothing is shifted in.
+            let ph = (ph << 1) & mask;
+            let mh = (mh << 1) & mask;
+            pv = (mh | !(xv | ph)) & mask;
+            mv = ph & xv;
+            if !f(j + 1, score) {
+                return;
+            }
+        }
+    }
+
+    // The first match in the text: where it ends and its distance. The end moves on while the distance doesn't get bigger,
+    // so a swap like "connectoin" is found whole, and not only "connecto" which is already 2 edits away from "connection".
+    fn first_match(&self, text: &[Unit]) -> Option<(usize, usize)> {
+        let mut found: Option<(usize, usize)> = None;
+        self.scan(text, |end, score| match found {
+            None => {
+                if score <= self.max_distance {
+                    found = Some((end, score));
+                }
+                true
+            }
+            Some((_, distance)) if score <= distance => {
+                found = Some((end, score));
+                true
+            }
+            Some(_) => false,
+        });
+        found
+    }
+
+    // The length of the shortest part of the text that ends at its end and is `distance` away from the query.
+    // It is the same table again, with the query and the text reversed so every part starts at the end.
+    fn match_length(&self, text: &[Unit], distance: usize) -> usize {
+        let m = self.query.len();
+        // A part longer than the query plus the edits can't be close enough.
+        let longest = text.len().min(m + self.max_distance);
+        let mut row: Vec<usize> = (0..=longest).collect();
+        for i in 1..=m {
+            let mut diagonal = row[0];
+            row[0] = i;
+            for j in 1..=longest {
+                let cost = usize::from(self.query[m - i] != text[text.len() - j].c);
+                let value = (diagonal + cost).min(row[j] + 1).min(row[j - 1] + 1);
+                diagonal = row[j];
+                row[j] = value;
+            }
+        }
+        row.iter().position(|&d| d == distance).unwrap_or(longest)
+    }
+
+    /// Returns every non-overlapping match in the line from left to right, the spans are byte ranges of whole characters.
+    pub fn find_iter(&self, line: &str) -> Vec<FuzzyMatch> {
+        let units = self.units(line);
+        // The byte offset of the unit, or of the next character when the unit is inside one.
+        let offset = |index: usize| {
+            units[index..]
+                .iter()
+                .find(|unit| unit.first)
+                .map_or(line.len(), |unit| unit.offset)
+        };
+
+        // A query that is short enough to delete completely matches everything, even an empty line.
+        if self.query.len() <= self.max_distance {
+            let mut distance = self.query.len();
+            // An empty query is already at distance 0 everywhere, and scan needs at least one row.
+            if !self.query.is_empty() {
+                self.scan(&units, |_, score| {
+                    distance = distance.min(score);
+                    true
+                });
+            }
+            return vec![FuzzyMatch {
+                start: 0,
+                end: 0,
+                distance,
+            }];
+        }
+
+        let mut matches = Vec::new();
+        let mut position = 0;
+        while let Some((end, distance)) = self.first_match(&units[position..]) {
+            let end = position + end;
+            let length = self.match_length(&units[position..end], distance);
+            // The query is longer than the distance, so a match is never empty and the loop always moves on.
+            let start = end - length;
+            let start_offset = units[start].offset;
+            matches.push(FuzzyMatch {
+                start: start_offset,
+                end: offset(end),
+                distance,
+            });
+            position = end;
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, k: usize, line: &str) -> Vec<(usize, usize, usize)> {
+        Fuzzy::build(query, k, false)
+            .find_iter(line)
+            .into_iter()
+            .map(|m| (m.start, m.end, m.distance))
+            .collect()
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(find("connection", 0, "connection lost"), vec![(0, 10, 0)]);
+        assert_eq!(find("connection", 1, "conection lost"), vec![(0, 9, 1)]);
+        assert_eq!(find("connection", 1, "connectoin lost"), vec![]);
+        assert_eq!(find("connection", 2, "connectoin lost"), vec![(0, 10, 2)]);
+        assert_eq!(
+            find("kitten", 2, "sitting kitten"),
+            vec![(1, 6, 2), (8, 14, 0)]
+        );
+        assert_eq!(find("ab", 2, ""), vec![(0, 0, 2)]);
+        assert_eq!(find("", 1, "abc"), vec![(0, 0, 0)]);
+    }
+
+    #[test]
+    fn long_queries_and_unicode() {
+        // More than 64 characters, so the plain table is used instead of the bits.
+        let query = "a".repeat(70);
+        let line = format!("xx{}b{}", "a".repeat(35), "a".repeat(34));
+        assert_eq!(find(&query, 1, &line), vec![(2, 72, 1)]);
+
+        assert_eq!(find("Grüße", 1, "viele Grüse"), vec![(6, 12, 1)]);
+        let fuzzy = Fuzzy::build("GRÜSSE", 1, true);
+        assert_eq!(
+            fuzzy.find_iter("viele Grüße")[0],
+            FuzzyMatch {
+                start: 6,
+                end: 13,
+                distance: 0
+            }
+        );
+    }
+}
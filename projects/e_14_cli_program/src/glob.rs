@@ -0,0 +1,230 @@
+use std::{error::Error, fmt};
+
+// Globs are the patterns of shells and .gitignore files, matched against paths with '/' between the components.
+// - `?` matches one character and `*` matches any number of characters, but neither of them matches '/'.
+// - `**` matches anything, '/' included, and `**/` matches zero or more whole directories, so `**/a` matches "a" and "x/y/a".
+// - `[abc]`, `[a-z]` and `[!a-z]` (or `[^a-z]`) match one character from a set, or not from it.
+// - `\` makes the next character literal, like `\*` or `\[`.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobError {
+    pub message: String,
+    pub position: usize, // The character position in the pattern where the error was found.
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "glob parse error at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl Error for GlobError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    Any,     // ?
+    Star,    // *
+    AnyPath, // **
+    AnyDirs, // **/
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+impl Token {
+    // Only for the tokens that match exactly one character.
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => c == *expected,
+            Token::Any => c != '/',
+            Token::Class { ranges, negated } => {
+                c != '/' && ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+            }
+            Token::Star | Token::AnyPath | Token::AnyDirs => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn build(pattern: &str) -> Result<Glob, GlobError> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let error = |message: &str, position| GlobError {
+            message: message.to_string(),
+            position,
+        };
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    // `**` is only special as a whole component, in a name like a**b it is the same as `*`.
+                    let starts_component = i == 0 || chars[i - 1] == '/';
+                    let next = chars.get(i + 2);
+                    tokens.push(match next {
+                        Some('/') if starts_component => {
+                            i += 1;
+                            Token::AnyDirs
+                        }
+                        None if starts_component => Token::AnyPath,
+                        _ => Token::Star,
+                    });
+                    i += 2;
+                    continue;
+                }
+                '*' => tokens.push(Token::Star),
+                '?' => tokens.push(Token::Any),
+                '\\' => {
+                    i += 1;
+                    let c = *chars
+                        .get(i)
+                        .ok_or_else(|| error("nothing to escape at the end of the pattern", i))?;
+                    tokens.push(Token::Char(c));
+                }
+                '[' => {
+                    let start = i;
+                    i += 1;
+                    let negated = matches!(chars.get(i), Some('!' | '^'));
+                    if negated {
+                        i += 1;
+                    }
+                    let mut ranges = Vec::new();
+                    // A ']' right at the start is a character of the set, not its end.
+                    let mut first = true;
+                    loop {
+                        let low = match chars.get(i) {
+                            None => return Err(error("unclosed character class", start)),
+                            Some(']') if !first => break,
+                            Some('\\') => {
+                                i += 1;
+                                *chars
+                                    .get(i)
+                                    .ok_or_else(|| error("unclosed character class", start))?
+                            }
+                            Some(&c) => c,
+                        };
+                        first = false;
+                        i += 1;
+                        let high = match (chars.get(i), chars.get(i + 1)) {
+                            (Some('-'), Some(&high)) if high != ']' => {
+                                i += 2;
+                                high
+                            }
+                            _ => low,
+                        };
+                        if high < low {
+                            return Err(error("invalid range in character class", i - 1));
+                        }
+                        ranges.push((low, high));
+                    }
+                    tokens.push(Token::Class { ranges, negated });
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            tokens,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns true if the glob matches the whole path, the components of the path must be separated by '/'.
+    pub fn is_match(&self, path: &str) -> bool {
+        // Going from the last token to the first, rest[j] is true when the tokens after the current one match path[j..].
+        // Every token is looked at once for every position, so there is no exponential backtracking with many stars.
+        let path: Vec<char> = path.chars().collect();
+        let n = path.len();
+        let mut rest = vec![false; n + 1];
+        rest[n] = true;
+        for token in self.tokens.iter().rev() {
+            let mut current = vec![false; n + 1];
+            match token {
+                Token::Star | Token::AnyPath => {
+                    for j in (0..=n).rev() {
+                        let can_take = j < n && (*token == Token::AnyPath || path[j] != '/');
+                        current[j] = rest[j] || (can_take && current[j + 1]);
+                    }
+                }
+                Token::AnyDirs => {
+                    // Some '/' at or after j ends the directories, and the rest matches after it.
+                    let mut found = false;
+                    for j in (0..=n).rev() {
+                        found |= j < n && path[j] == '/' && rest[j + 1];
+                        current[j] = rest[j] || found;
+                    }
+                }
+                _ => {
+                    for j in 0..n {
+                        current[j] = token.matches(path[j]) && rest[j + 1];
+                    }
+                }
+            }
+            rest = current;
+        }
+        rest[0]
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        Glob::build(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn stars_and_classes() {
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(!is_match("*.rs", "src/main.rs"));
+        assert!(is_match("src/?ain.[rR][!a-z]", "src/main.rS"));
+        assert!(!is_match("src/?ain.[rR][!a-z]", "src/main.rs"));
+        assert!(is_match("[]a]", "]"));
+        assert!(is_match(r"\*", "*"));
+        assert!(!is_match(r"\*", "a"));
+        assert!(is_match("a*b*c*d", "axxbyyczzd"));
+    }
+
+    #[test]
+    fn double_stars() {
+        assert!(is_match("**/*.rs", "main.rs"));
+        assert!(is_match("**/*.rs", "src/bin/main.rs"));
+        assert!(is_match("target/**", "target/debug/minigrep"));
+        assert!(is_match("target/**", "target/"));
+        assert!(!is_match("target/**", "targets/a"));
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(!is_match("a/**/b", "a/xb"));
+        // Inside a name ** is only a *.
+        assert!(!is_match("a**b", "a/b"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Glob::build("[abc").unwrap_err().position, 0);
+        assert!(Glob::build("a\\").is_err());
+        assert!(Glob::build("[z-a]").is_err());
+    }
+}
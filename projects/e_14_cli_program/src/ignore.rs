@@ -0,0 +1,238 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{glob::Glob, index::INDEX_FILE};
+
+// The files a recursive search skips. Paths are matched relative to the directory given on the command line,
+// with '/' between the components, so the same globs work on every platform.
+//
+// .gitignore and .ignore files are read as the walk enters each directory and follow the .gitignore rules:
+// - Blank lines and lines starting with '#' are skipped, `\#` and `\!` start a pattern with a literal '#' or '!'.
+// - A pattern with a '/' at the start or in the middle is relative to the directory of the file,
+//   any other pattern matches a name at any depth below it, like `**/pattern`.
+// - A pattern ending with '/' only matches directories.
+// - `!pattern` includes again what an earlier pattern excluded, the last pattern that matches a path wins.
+// Rules from a deeper directory come later, so they win over the ones above them, and .ignore wins over .gitignore.
+// Like git, the ignore files of the directories above the searched one are used too, up to the root of the repository.
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+#[derive(Debug, Clone)]
+struct Rule {
+    base: PathBuf, // The directory of the file the rule comes from.
+    // For the files above the searched directory, base is the searched directory and
+    // prefix is the way from the ignore file's directory down to it, like "projects/minigrep".
+    prefix: String,
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    // Returns None for the lines that aren't rules, and for invalid patterns which git skips too.
+    fn parse(base: &Path, line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let pattern = match line.strip_prefix('/') {
+            Some(line) => line.to_string(),
+            None if line.contains('/') => line.to_string(),
+            None => format!("**/{line}"),
+        };
+        Some(Rule {
+            base: base.to_path_buf(),
+            prefix: String::new(),
+            glob: Glob::build(&pattern).ok()?,
+            negated,
+            dir_only,
+        })
+    }
+}
+
+/// Decides which files and directories a recursive search skips, from `--include`, `--exclude`
+/// and the .gitignore and .ignore files found along the walk.
+///
+/// The paths given on the command line are always searched, the filter only applies to what is found inside directories.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    ignore_files: bool,
+    rules: Vec<Rule>,
+}
+
+impl Filter {
+    /// Like grep, `include` and `exclude` match at any depth: `*.rs` matches "src/main.rs"
+    /// and `target/**` matches everything in any target directory. A glob starting with '/' only matches from the top.
+    /// With `ignore_files` the .gitignore and .ignore files are read, and .git directories and indexes are skipped.
+    pub fn new(include: &[Glob], exclude: &[Glob], ignore_files: bool) -> Filter {
+        // The globs were already checked when they were given, so anchoring them can't make them invalid.
+        let anchor = |glob: &Glob| match glob.pattern().strip_prefix('/') {
+            Some(pattern) => Glob::build(pattern).unwrap(),
+            None => Glob::build(&format!("**/{}", glob.pattern())).unwrap(),
+        };
+        Filter {
+            include: include.iter().map(anchor).collect(),
+            exclude: exclude.iter().map(anchor).collect(),
+            ignore_files,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Reads the ignore files of a directory the walk is entering, the rules apply to everything below it.
+    pub fn enter_dir(&mut self, dir: &Path) -> io::Result<()> {
+        if self.ignore_files {
+            let rules = read_rules(dir, dir)?;
+            self.rules.extend(rules);
+        }
+        Ok(())
+    }
+
+    /// Reads the ignore files of the directories above `root`, up to the one with the .git directory.
+    /// When `root` isn't inside a git repository nothing is read.
+    pub fn enter_root(&mut self, root: &Path) -> io::Result<()> {
+        if !self.ignore_files {
+            return Ok(());
+        }
+        let absolute = fs::canonicalize(root)?;
+        let mut above = Vec::new();
+        for dir in absolute.ancestors().skip(1) {
+            let prefix = slash_path(absolute.strip_prefix(dir).unwrap());
+            let mut rules = read_rules(dir, root)?;
+            for rule in &mut rules {
+                rule.prefix = prefix.clone();
+            }
+            above.push(rules);
+            if dir.join(".git").exists() {
+                // The outermost rules go first, so the ones closer to root win.
+                self.rules.extend(above.into_iter().rev().flatten());
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if the walk should skip the path, `root` is the path given on the command line it was found in.
+    pub fn is_excluded(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let name = path.file_name().and_then(|name| name.to_str());
+        // The index of `minigrep index` isn't text anyone wrote, it is skipped even with --no-ignore.
+        if !is_dir && name == Some(INDEX_FILE) {
+            return true;
+        }
+        if self.ignore_files && is_dir && name == Some(".git") {
+            return true;
+        }
+
+        let relative = slash_path(path.strip_prefix(root).unwrap_or(path));
+        // A directory is also matched with a '/' at the end, so `target/**` skips the whole directory.
+        let matches = |glob: &Glob| {
+            glob.is_match(&relative) || (is_dir && glob.is_match(&format!("{relative}/")))
+        };
+        if self.exclude.iter().any(matches) {
+            return true;
+        }
+        if !is_dir && !self.include.is_empty() && !self.include.iter().any(matches) {
+            return true;
+        }
+
+        // The last rule that matches decides.
+        for rule in self.rules.iter().rev() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            let relative = match (rule.prefix.as_str(), slash_path(relative)) {
+                ("", relative) => relative,
+                (prefix, relative) => format!("{prefix}/{relative}"),
+            };
+            if rule.glob.is_match(&relative) {
+                return !rule.negated;
+            }
+        }
+        false
+    }
+}
+
+// Reads the rules of the ignore files in dir, base is the directory the rules are relative to.
+fn read_rules(dir: &Path, base: &Path) -> io::Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for name in IGNORE_FILES {
+        let contents = match fs::read_to_string(dir.join(name)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        rules.extend(contents.lines().filter_map(|line| Rule::parse(base, line)));
+    }
+    Ok(rules)
+}
+
+// Joins the components of the path with '/', whatever the separator of the platform is.
+pub(crate) fn slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(patterns: &[&str]) -> Vec<Glob> {
+        patterns.iter().map(|p| Glob::build(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = Filter::new(&globs(&["*.rs"]), &globs(&["target/**"]), false);
+        let root = Path::new("project");
+        let excluded = |path: &str, is_dir| filter.is_excluded(root, &root.join(path), is_dir);
+        assert!(!excluded("src/main.rs", false));
+        assert!(excluded("README.md", false));
+        assert!(!excluded("src", true)); // --include doesn't stop the walk from entering directories.
+        assert!(excluded("target", true));
+        assert!(excluded("crate/target", true));
+        assert!(!excluded("targets", true));
+    }
+
+    #[test]
+    fn gitignore_rules() {
+        let mut filter = Filter::new(&[], &[], true);
+        let root = Path::new("project");
+        let rules = "# build output\n/target\n*.log\n!keep.log\nout/\ndocs/*.html\n";
+        filter
+            .rules
+            .extend(rules.lines().filter_map(|line| Rule::parse(root, line)));
+        let sub = root.join("sub");
+        filter.rules.extend(Rule::parse(&sub, "!*.log"));
+
+        let excluded = |path: &str, is_dir| filter.is_excluded(root, &root.join(path), is_dir);
+        assert!(excluded("target", true));
+        assert!(!excluded("crate/target", true)); // Anchored to the directory of the .gitignore.
+        assert!(excluded("a/b/debug.log", false));
+        assert!(!excluded("keep.log", false));
+        assert!(!excluded("sub/debug.log", false)); // The deeper file wins.
+        assert!(excluded("x/out", true));
+        assert!(!excluded("x/out", false)); // Only directories.
+        assert!(excluded("docs/index.html", false));
+        assert!(!excluded("docs/api/index.html", false));
+        assert!(excluded(".git", true));
+    }
+}
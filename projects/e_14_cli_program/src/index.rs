@@ -0,0 +1,386 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, Metadata},
+    io,
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    error::Warnings, fold, ignore::slash_path, replace::write_atomically, walk::Walk, Filter,
+    MinigrepError, SearchOptions,
+};
+
+// A trigram index remembers, for every file of a directory, which sequences of 3 bytes (trigrams) appear in it.
+// A line that contains the query contains every trigram of the query, so a file missing one of them can't match
+// and doesn't have to be read at all. The files that are left are still searched normally, the index only
+// throws away files, it never decides that a line matches.
+//
+// - The trigrams come from the case folded text (see fold), so the same index works with and without -i:
+//   folding is done one character at a time, so the folded query is still inside the folded line.
+// - Every file is stored with its size and modification time. A file that changed since it was indexed,
+//   or that isn't in the index yet, is always searched, so an old index usually only makes a search slower.
+//   But a change is only noticed through the size and the time: a rewrite that keeps the size within the
+//   same modification time (file systems with coarse timestamps make that easier) looks unchanged,
+//   and the file can be wrongly skipped until `minigrep index` runs again.
+// - `minigrep index` only reads the files whose size or modification time changed since the last time.
+//
+// The index is a binary file in the indexed directory, all the numbers are little endian:
+//   MAGIC, the number of files, and for every file: the length of its path, the path (relative, with '/'),
+//   the modification time (seconds and nanoseconds since 1970), the size, the number of trigrams and the trigrams.
+
+pub const INDEX_FILE: &str = ".minigrep-index";
+const MAGIC: &[u8] = b"minigrep index 1\n";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    modified: (u64, u32),
+    size: u64,
+    trigrams: Vec<u32>, // Sorted, so a trigram can be found with a binary search.
+}
+
+impl Entry {
+    fn is_fresh(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && Some(self.modified) == modified(metadata)
+    }
+
+    fn contains_all(&self, trigrams: &[u32]) -> bool {
+        trigrams
+            .iter()
+            .all(|trigram| self.trigrams.binary_search(trigram).is_ok())
+    }
+}
+
+fn modified(metadata: &Metadata) -> Option<(u64, u32)> {
+    let time = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((time.as_secs(), time.subsec_nanos()))
+}
+
+/// Returns the sorted trigrams of the case folded text, the ones that cross a line ending are skipped
+/// because minigrep matches one line at a time.
+pub fn trigrams(text: &str) -> Vec<u32> {
+    let folded = fold::fold(text);
+    let mut trigrams: Vec<u32> = folded
+        .as_bytes()
+        .windows(3)
+        .filter(|window| !window.contains(&b'\n'))
+        .map(|w| u32::from(w[0]) << 16 | u32::from(w[1]) << 8 | u32::from(w[2]))
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// The trigrams a file must have to possibly match the queries: all the trigrams of at least one query.
+#[derive(Debug, PartialEq)]
+pub struct TrigramQuery {
+    alternatives: Vec<Vec<u32>>,
+}
+
+impl TrigramQuery {
+    /// Returns None when the index can't help and every file has to be searched: for regexes and --fuzzy,
+    /// with -v (the lines without the query are selected), and for queries shorter than a trigram.
+    /// With `lossy` the searched text isn't the bytes that were indexed, so the index isn't used either.
+    pub fn build<S: AsRef<str>>(
+        queries: &[S],
+        options: SearchOptions,
+        lossy: bool,
+    ) -> Option<TrigramQuery> {
+        if options.regex || options.fuzzy.is_some() || options.invert_match || lossy {
+            return None;
+        }
+        let mut alternatives = Vec::with_capacity(queries.len());
+        for query in queries {
+            let trigrams = trigrams(query.as_ref());
+            if trigrams.is_empty() {
+                return None;
+            }
+            alternatives.push(trigrams);
+        }
+        Some(TrigramQuery { alternatives })
+    }
+}
+
+/// What `Index::update` did.
+#[derive(Debug, Default, PartialEq)]
+pub struct Updated {
+    pub files: usize,
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+}
+
+/// The trigram index of a directory, see `minigrep index`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    files: BTreeMap<String, Entry>, // By path, relative to the indexed directory.
+}
+
+impl Index {
+    /// Reads the index of the directory, None if it doesn't have one.
+    pub fn load(dir: &Path) -> io::Result<Option<Index>> {
+        match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => Index::decode(&bytes).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Builds the index of the directory, or updates the one it already has, and saves it in the directory.
+    /// Only the new files and the ones whose size or modification time changed are read.
+    /// The files that can't be read are reported as warnings and left out.
+    pub(crate) fn update(dir: &Path, filter: Filter, warnings: &Warnings) -> io::Result<Updated> {
+        // A missing directory is one error, instead of a warning from the walk and another one when saving.
+        fs::read_dir(dir)?;
+        // A broken index (from an older minigrep, or a disk problem) is built again from nothing.
+        let mut old = match Index::load(dir) {
+            Ok(index) => index.unwrap_or_default(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => Index::default(),
+            Err(e) => return Err(e),
+        };
+        let mut index = Index::default();
+        let mut updated = Updated::default();
+        for path in Walk::with_filter(&[dir], filter) {
+            let path = match path {
+                Ok(path) => path,
+                Err(e) => {
+                    warnings.report(MinigrepError::from(e));
+                    continue;
+                }
+            };
+            let key = slash_path(path.strip_prefix(dir).unwrap_or(&path));
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    warnings.report(MinigrepError::Io {
+                        path: path.display().to_string(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            let entry = match old.files.remove(&key) {
+                Some(entry) if entry.is_fresh(&metadata) => entry,
+                previous => {
+                    let bytes = match fs::read(&path) {
+                        Ok(bytes) => bytes,
+                        Err(error) => {
+                            warnings.report(MinigrepError::Io {
+                                path: path.display().to_string(),
+                                error,
+                            });
+                            continue;
+                        }
+                    };
+                    match previous {
+                        Some(_) => updated.changed += 1,
+                        None => updated.added += 1,
+                    }
+                    // Like the search, the bytes that aren't UTF-8 become U+FFFD.
+                    Entry {
+                        modified: modified(&metadata).unwrap_or_default(),
+                        size: metadata.len(),
+                        trigrams: trigrams(&String::from_utf8_lossy(&bytes)),
+                    }
+                }
+            };
+            index.files.insert(key, entry);
+        }
+        // What is left in the old index wasn't found by the walk anymore.
+        updated.removed = old.files.len();
+        updated.files = index.files.len();
+        write_atomically(&dir.join(INDEX_FILE), &index.encode())?;
+        Ok(updated)
+    }
+
+    /// Returns false only when the file can't match the query: the index has it unchanged and it is missing
+    /// a trigram of every query. `key` is the path of the file relative to the indexed directory.
+    pub fn may_match(&self, key: &str, metadata: &Metadata, query: &TrigramQuery) -> bool {
+        match self.files.get(key) {
+            Some(entry) if entry.is_fresh(metadata) => query
+                .alternatives
+                .iter()
+                .any(|trigrams| entry.contains_all(trigrams)),
+            _ => true,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((self.files.len() as u64).to_le_bytes());
+        for (path, entry) in &self.files {
+            bytes.extend((path.len() as u64).to_le_bytes());
+            bytes.extend(path.as_bytes());
+            bytes.extend(entry.modified.0.to_le_bytes());
+            bytes.extend(entry.modified.1.to_le_bytes());
+            bytes.extend(entry.size.to_le_bytes());
+            bytes.extend((entry.trigrams.len() as u64).to_le_bytes());
+            for trigram in &entry.trigrams {
+                bytes.extend(trigram.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Index> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a minigrep index"));
+        }
+        let mut files = BTreeMap::new();
+        for _ in 0..reader.u64()? {
+            let length = reader.u64()? as usize;
+            let path = String::from_utf8(reader.take(length)?.to_vec())
+                .map_err(|_| invalid("a path isn't UTF-8"))?;
+            let modified = (reader.u64()?, reader.u32()?);
+            let size = reader.u64()?;
+            let count = reader.u64()? as usize;
+            let mut trigrams = Vec::with_capacity(count.min(bytes.len() / 4));
+            for _ in 0..count {
+                trigrams.push(reader.u32()?);
+            }
+            files.insert(
+                path,
+                Entry {
+                    modified,
+                    size,
+                    trigrams,
+                },
+            );
+        }
+        if !reader.bytes.is_empty() {
+            return Err(invalid("unexpected bytes at the end"));
+        }
+        Ok(Index { files })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid index: {message}"),
+    )
+}
+
+// Takes the fields of the index from the front of the bytes, a file cut short is an error and not a panic.
+struct Reader<'b> {
+    bytes: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, length: usize) -> io::Result<&'b [u8]> {
+        if self.bytes.len() < length {
+            return Err(invalid("the file is cut short"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn query(queries: &[&str], ignore_case: bool) -> TrigramQuery {
+        let options = SearchOptions {
+            ignore_case,
+            ..SearchOptions::default()
+        };
+        TrigramQuery::build(queries, options, false).unwrap()
+    }
+
+    #[test]
+    fn trigrams_and_queries() {
+        let abc = |a: u8, b: u8, c: u8| u32::from(a) << 16 | u32::from(b) << 8 | u32::from(c);
+        assert_eq!(
+            trigrams("abcab\nab"),
+            vec![
+                abc(b'a', b'b', b'c'),
+                abc(b'b', b'c', b'a'),
+                abc(b'c', b'a', b'b')
+            ]
+        );
+        assert!(trigrams("STRASSE").iter().eq(trigrams("straße").iter()));
+        assert_eq!(
+            TrigramQuery::build(&["ab"], SearchOptions::default(), false),
+            None
+        );
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(TrigramQuery::build(&["nobody"], regex, false), None);
+
+        let entry = Entry {
+            modified: (0, 0),
+            size: 0,
+            trigrams: trigrams("I'm nobody! Who are you?"),
+        };
+        let matches = |query: &TrigramQuery| {
+            query
+                .alternatives
+                .iter()
+                .any(|trigrams| entry.contains_all(trigrams))
+        };
+        assert!(matches(&query(&["NOBODY"], true)));
+        assert!(!matches(&query(&["somebody"], false)));
+        assert!(matches(&query(&["somebody", "who are"], false)));
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let dir = env::temp_dir().join(format!("minigrep-index-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "connection refused").unwrap();
+        fs::write(dir.join("sub/b.txt"), "timeout").unwrap();
+
+        let filter = Filter::new(&[], &[], true);
+        let warnings = Warnings::default();
+        let updated = Index::update(&dir, filter.clone(), &warnings).unwrap();
+        assert_eq!((updated.files, updated.added), (2, 2));
+
+        let index = Index::load(&dir).unwrap().unwrap();
+        let timeout = query(&["timeout"], false);
+        let metadata = |name: &str| fs::metadata(dir.join(name)).unwrap();
+        assert!(!index.may_match("a.txt", &metadata("a.txt"), &timeout));
+        assert!(index.may_match("sub/b.txt", &metadata("sub/b.txt"), &timeout));
+
+        // A changed file is searched until the index is updated, and the update only reads that file.
+        fs::write(dir.join("a.txt"), "timeout again").unwrap();
+        assert!(index.may_match("a.txt", &metadata("a.txt"), &timeout));
+        fs::remove_file(dir.join("sub/b.txt")).unwrap();
+        let updated = Index::update(&dir, filter, &warnings).unwrap();
+        assert_eq!(
+            updated,
+            Updated {
+                files: 1,
+                added: 0,
+                changed: 1,
+                removed: 1,
+            }
+        );
+        assert!(!warnings.any());
+        assert_eq!(Index::load(&dir).unwrap().unwrap().files.len(), 1);
+        // The index itself is never indexed, even when the ignore files aren't used.
+        let no_ignore = Filter::new(&[], &[], false);
+        assert_eq!(Index::update(&dir, no_ignore, &warnings).unwrap().files, 1);
+
+        fs::write(dir.join(INDEX_FILE), b"minigrep index 1\n\x05").unwrap();
+        assert_eq!(
+            Index::load(&dir).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
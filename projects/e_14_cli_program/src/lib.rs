@@ -1,77 +1,352 @@
-use std::{env, error::Error, fs};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    thread,
+};
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
-}
+pub mod aho_corasick;
+pub mod config;
+pub mod context;
+pub mod decode;
+pub mod error;
+pub mod fold;
+pub mod fuzzy;
+pub mod glob;
+pub mod ignore;
+pub mod index;
+pub mod json;
+pub mod output;
+mod parallel;
+pub mod pool;
+pub mod regex;
+pub mod replace;
+pub mod searcher;
+pub mod stream;
+pub mod walk;
+mod watch;
 
-impl Config {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        //if args.len() < 3 {
-        //    return Err("Not enough arguments!");
-        //}
-        // We need to clone the values because we are borrowing them from the args vector,
-        // if we don't clone them, the ownership of the values will be moved to the Config struct
-        // which is a violation of the borrowing rules.
-        // This operation might be expensive if the values are large.
-        //let query = args[1].clone();
-        //let file_path = args[2].clone();
-
-        // After refactoring using an iterator.
-        args.next(); // Skip the first argument which is the program name.
-
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Query not found!"),
-        };
+pub use config::{ColorChoice, Config, ConfigError, OutputMode};
+use error::Warnings;
+pub use error::{MinigrepError, Outcome};
+use ignore::Filter;
+use index::{Index, TrigramQuery};
+use output::Printer;
+use regex::Regex;
+pub use replace::{replace, Edit, Replaced};
+pub use searcher::{Matcher, SearchOptions, Searcher};
+use stream::search_reader;
+use walk::Walk;
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("File path not found!"),
-        };
-
-        // The env::var function returns a Result type, if the variable is not found it returns an Err value.
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+/// Searches like the config says and prints the results.
+///
+/// The errors that stop everything are returned, like an invalid pattern or an output that can't be written.
+/// A path that can't be searched is only a warning, the others are still searched and the outcome says there was a problem.
+pub fn run(config: Config) -> Result<Outcome, MinigrepError> {
+    // The ? operator is used to propagate errors up the call stack.
+    // It also converts them with From, so a RegexError becomes a MinigrepError::Pattern.
+    let warnings = Warnings::default();
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+    // minigrep index doesn't search, it builds or updates the index of every directory.
+    if config.index {
+        // Like a search, a directory that fails doesn't stop the others from being indexed.
+        for path in &config.paths {
+            let updated = match Index::update(Path::new(path), config.filter(), &warnings) {
+                Ok(updated) => updated,
+                Err(error) => {
+                    warnings.report(MinigrepError::Io {
+                        path: path.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            println!(
+                "{path}: {} files indexed ({} new, {} changed, {} removed)",
+                updated.files, updated.added, updated.changed, updated.removed
+            );
+        }
+        return Ok(Outcome::new(true, &warnings));
     }
-}
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    // Box<dyn Error> is a trait object, it means that the function can return any type that implements the Error trait.
-    // dyn is short for dynamic, it means that the type is determined at runtime.
-    // The ? operator is used to propagate errors up the call stack.
-    let contents = fs::read_to_string(config.file_path)?;
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+    // The patterns are compiled once and then used for every line of every file.
+    let queries = config.queries()?;
+    let searcher = Searcher::build_many(&queries, config.search_options())?;
+    // Directories with an index only have their candidate files searched.
+    let trigrams = TrigramQuery::build(&queries, config.search_options(), config.lossy);
+
+    // Like grep, no paths means reading from stdin, and "-" can be used to mix stdin with other paths.
+    let paths = if config.paths.is_empty() {
+        vec![String::from("-")]
     } else {
-        search(&config.query, &contents)
+        config.paths.clone()
     };
-    for line in results {
-        println!("{}", line);
+
+    // Replacing has its own output, a diff or the list of changed files, so it doesn't use the Printer.
+    if let Some(replacement) = &config.replace {
+        let mut out = BufWriter::new(io::stdout().lock());
+        let replaced = replace::run_replace(
+            &config,
+            &searcher,
+            &paths,
+            trigrams.as_ref(),
+            &warnings,
+            replacement,
+            &mut out,
+        )
+        .map_err(MinigrepError::Output)?;
+        return Ok(Outcome::new(replaced, &warnings));
     }
-    Ok(())
+
+    // Like grep, the file name is only printed when more than one file can be searched.
+    let show_path = paths.len() > 1 || paths.iter().any(|path| Path::new(path).is_dir());
+
+    // println! flushes after every line, a BufWriter is much faster when there are many matches.
+    let stdout = io::stdout();
+    let color = config.color.enabled(stdout.is_terminal());
+    let mut printer = Printer::new(&config, BufWriter::new(stdout.lock()), show_path, color);
+
+    if config.watch {
+        watch::watch(&config, &searcher, &mut printer, &paths).map_err(MinigrepError::Output)?;
+        return Ok(Outcome::new(printer.matched(), &warnings));
+    }
+
+    // Threads only help when there is more than one file, a single file or stdin is searched as it is read.
+    let jobs = config
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+    if jobs > 1 && show_path {
+        parallel::search_parallel(
+            &config,
+            searcher,
+            &mut printer,
+            &paths,
+            trigrams.as_ref(),
+            &warnings,
+            jobs,
+        )?;
+        printer.summary().map_err(MinigrepError::Output)?;
+        return Ok(Outcome::new(printer.matched(), &warnings));
+    }
+
+    search_sequential(
+        &config,
+        &searcher,
+        &mut printer,
+        &paths,
+        trigrams.as_ref(),
+        &warnings,
+    )
+    .map_err(MinigrepError::Output)?;
+    Ok(Outcome::new(printer.matched(), &warnings))
+}
+
+// Searches the inputs one after the other, only an error while writing is returned.
+fn search_sequential<W: Write>(
+    config: &Config,
+    searcher: &Searcher,
+    printer: &mut Printer<W>,
+    paths: &[String],
+    trigrams: Option<&TrigramQuery>,
+    warnings: &Warnings,
+) -> io::Result<()> {
+    for input in inputs(paths, &config.filter(), trigrams, warnings) {
+        match input {
+            Input::Stdin => {
+                let stdin = io::stdin();
+                search_input(
+                    config,
+                    searcher,
+                    printer,
+                    warnings,
+                    STDIN_NAME,
+                    stdin.lock(),
+                )?;
+            }
+            Input::File(path) => {
+                let name = path.display().to_string();
+                let file = match File::open(&path) {
+                    Ok(file) => file,
+                    Err(error) => {
+                        warnings.report(MinigrepError::Io { path: name, error });
+                        continue;
+                    }
+                };
+                search_input(
+                    config,
+                    searcher,
+                    printer,
+                    warnings,
+                    &name,
+                    BufReader::new(file),
+                )?;
+            }
+            Input::Unmatched(path) => {
+                let name = path.display().to_string();
+                search_input(config, searcher, printer, warnings, &name, io::empty())?;
+            }
+        }
+    }
+    printer.summary()
+}
+
+// Something to search, run turns the paths into inputs before searching them one by one or in the pool.
+pub(crate) enum Input {
+    Stdin,
+    File(PathBuf),
+    // A file the trigram index rules out. It isn't read, but -c and --json still report it as a file without matches.
+    Unmatched(PathBuf),
+}
+
+// Walks the paths in order, a path we can't walk is reported as a warning and the rest are still searched.
+// When a directory has a trigram index (see `minigrep index`), the files it rules out aren't read.
+pub(crate) fn inputs<'p>(
+    paths: &'p [String],
+    filter: &'p Filter,
+    trigrams: Option<&'p TrigramQuery>,
+    warnings: &'p Warnings,
+) -> impl Iterator<Item = Input> + 'p {
+    paths
+        .iter()
+        .flat_map(move |path| -> Box<dyn Iterator<Item = Input>> {
+            if path == "-" {
+                return Box::new(std::iter::once(Input::Stdin));
+            }
+            let root = Path::new(path);
+            // A broken index is only a warning, the directory is searched without it.
+            let index = match trigrams {
+                Some(trigrams) if root.is_dir() => Index::load(root)
+                    .unwrap_or_else(|error| {
+                        let path = path.clone();
+                        warnings.report(MinigrepError::Io { path, error });
+                        None
+                    })
+                    .map(|index| (index, trigrams)),
+                _ => None,
+            };
+            Box::new(Walk::with_filter(&[path], filter.clone()).filter_map(
+                move |file| match file {
+                    Ok(file) => {
+                        if let Some((index, trigrams)) = &index {
+                            let key = ignore::slash_path(file.strip_prefix(root).unwrap_or(&file));
+                            let metadata = fs::metadata(&file);
+                            if metadata.is_ok_and(|m| !index.may_match(&key, &m, trigrams)) {
+                                return Some(Input::Unmatched(file));
+                            }
+                        }
+                        Some(Input::File(file))
+                    }
+                    Err(e) => {
+                        warnings.report(e.into());
+                        None
+                    }
+                },
+            ))
+        })
+}
+
+pub(crate) const STDIN_NAME: &str = "(standard input)";
+
+// Searches one input, an error while reading it is only a warning but an error while writing stops the whole run.
+fn search_input<R: BufRead, W: Write>(
+    config: &Config,
+    searcher: &Searcher,
+    printer: &mut Printer<W>,
+    warnings: &Warnings,
+    name: &str,
+    reader: R,
+) -> io::Result<()> {
+    printer.start(name)?;
+    let mut write_failed = false;
+    let result = search_reader(searcher, reader, config.read_options(), |line| {
+        printer.line(&line).inspect_err(|_| write_failed = true)
+    });
+    match result {
+        Ok(summary) => printer.finish(summary),
+        Err(e) if write_failed => Err(e),
+        Err(error) => {
+            let path = name.to_string();
+            warnings.report(MinigrepError::Io { path, error });
+            Ok(())
+        }
+    }
+}
+
+/// A line that matched, with everything needed to print it.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    pub line_number: usize, // Starts at 1 like in editors and grep.
+    pub offset: usize,      // The byte offset where the line starts in the contents.
+    pub line: &'a str,
+    pub spans: Vec<(usize, usize)>, // The byte ranges of the matches inside the line.
+}
+
+/// Like search, but keeps the position of every matching line and of the matches inside it.
+pub fn search_matches<'a>(searcher: &Searcher, contents: &'a str) -> Vec<Match<'a>> {
+    lines_with_offsets(contents)
+        .enumerate()
+        .filter_map(|(index, (offset, line))| {
+            let spans = searcher.matches(line)?;
+            Some(Match {
+                line_number: index + 1,
+                offset,
+                line,
+                spans,
+            })
+        })
+        .collect()
+}
+
+// Works like contents.lines() but also returns where each line starts.
+pub(crate) fn lines_with_offsets(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    contents.split_inclusive('\n').map(move |line| {
+        let start = offset;
+        offset += line.len();
+        let line = match line.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => line,
+        };
+        (start, line)
+    })
 }
 
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     // The lifetime 'a is the lifetime of the reference to the contents parameter.
     // The return value of this function is a vector of string slices that are references to the contents parameter.
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
+    // Only regular expressions can fail to build, so unwrap can't panic here.
+    Searcher::build(query, SearchOptions::default())
+        .unwrap()
+        .search(contents)
 }
 
 pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
+    let options = SearchOptions {
+        ignore_case: true,
+        ..SearchOptions::default()
+    };
+    Searcher::build(query, options).unwrap().search(contents)
+}
+
+/// Returns the lines with a part at most `max_distance` edits away from the query, the closest lines first.
+pub fn search_fuzzy<'a>(query: &str, contents: &'a str, max_distance: usize) -> Vec<&'a str> {
+    let options = SearchOptions {
+        fuzzy: Some(max_distance),
+        ..SearchOptions::default()
+    };
+    let searcher = Searcher::build(query, options).unwrap();
+    let mut lines: Vec<(usize, &str)> = contents
+        .lines()
+        .filter_map(|line| Some((searcher.distance(line)?, line)))
+        .collect();
+    lines.sort_by_key(|&(distance, _)| distance);
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<&'a str> {
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
+        .filter(|line| regex.is_match(line))
         .collect()
 }
 
@@ -95,4 +370,66 @@ mod tests {
             search_case_insensitive(query, contents)
         );
     }
+
+    #[test]
+    fn fuzzy_results() {
+        let contents = "connection refused\nconection reset\nconnectoin lost\nno network\n";
+        assert_eq!(
+            vec!["connection refused", "conection reset", "connectoin lost"],
+            search_fuzzy("connection", contents, 2)
+        );
+        // The closest lines come first: no edit, then one missing 'n'. The swap is too far from this query.
+        assert_eq!(
+            vec!["conection reset", "connection refused"],
+            search_fuzzy("conection", contents, 2)
+        );
+        assert_eq!(
+            vec!["connection refused"],
+            search_fuzzy("connection", contents, 0)
+        );
+    }
+
+    #[test]
+    fn fuzzy_edge_cases() {
+        // A query that can be deleted completely matches every line, even an empty one.
+        assert_eq!(vec!["", "abc"], search_fuzzy("xy", "\nabc", 2));
+        assert_eq!(Vec::<&str>::new(), search_fuzzy("abc", "", 1));
+        assert_eq!(vec!["naïve café"], search_fuzzy("naive", "naïve café", 1));
+        assert_eq!(vec!["", "abc"], search_fuzzy("", "\nabc", 1));
+        assert_eq!(vec!["", "abc"], search_fuzzy("", "\nabc", 0));
+    }
+
+    #[test]
+    fn regex_results() {
+        let regex = Regex::build(r"^ERROR \d+", false).unwrap();
+        let contents = "ERROR 500\nWARN 200\nERROR code\nnot an ERROR 404\n";
+        assert_eq!(vec!["ERROR 500"], search_regex(&regex, contents));
+    }
+
+    #[test]
+    fn structured_matches() {
+        let searcher = Searcher::build("us", SearchOptions::default()).unwrap();
+        let contents =
+            "I'm nobody! Who are you?\r\nThen there's a pair of us - don't tell!\nus and us";
+        let matches = search_matches(&searcher, contents);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line_number, 2);
+        assert_eq!(matches[0].offset, 26);
+        assert_eq!(matches[0].spans, vec![(23, 25)]);
+        assert_eq!(matches[1].line, "us and us");
+        assert_eq!(matches[1].spans, vec![(0, 2), (7, 9)]);
+    }
+
+    #[test]
+    fn case_insensitive_unicode() {
+        let contents = "Die Straße\nDIE STRASSE\nstrasbourg\nİstanbul";
+        assert_eq!(
+            vec!["Die Straße", "DIE STRASSE"],
+            search_case_insensitive("strasse", contents)
+        );
+        assert_eq!(
+            vec!["İstanbul"],
+            search_case_insensitive("İSTANBUL", contents)
+        );
+    }
 }
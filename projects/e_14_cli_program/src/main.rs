@@ -1,4 +1,4 @@
-use minigrep::{run, Config};
+use minigrep::{run, Config, ConfigError, MinigrepError};
 use std::{env, process};
 
 fn main() {
@@ -10,15 +10,31 @@ fn main() {
     // if it is Ok it returns the value, if it is Err it calls the closure
     // |err| is a closure that takes an argument err and returns a string
     let config = Config::build(env::args()).unwrap_or_else(|err| {
+        // --help and --version stop the parsing like an error, but they print to stdout and exit successfully.
+        if matches!(err, ConfigError::Help | ConfigError::Version) {
+            println!("{err}");
+            process::exit(0);
+        }
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(MinigrepError::Config(err).exit_code());
     });
     // If build doesn't implements the Iterator trait on the parameter we need to collect the arguments first
 
+    // The report goes to stderr, so it doesn't end up with the results.
+    if config.debug_config {
+        eprint!("{}", config.debug_report());
+    }
+
+    // Like grep, the exit code tells how it went: 0 when a line was selected, 1 when none was and 2 after an error.
     // If the run function returns an error, we will print the error message and exit the program.
-    // Here we don't need to use unwrap_or_else because the run function returns ()
-    if let Err(e) = run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    match run(config) {
+        Ok(outcome) => process::exit(outcome.exit_code()),
+        Err(e) => {
+            if !e.is_broken_pipe() {
+                eprintln!("Application error: {e}");
+            }
+            process::exit(e.exit_code());
+        }
     }
 }
@@ -0,0 +1,376 @@
+use std::io::{self, Write};
+
+use crate::{context::Line, json::Json, stream::Summary, Config, OutputMode};
+
+// ANSI escape codes, with the same colors grep uses by default.
+const MATCH_COLOR: &str = "\x1b[1;31m"; // Bold red
+const PATH_COLOR: &str = "\x1b[35m"; // Magenta
+const LINE_NUMBER_COLOR: &str = "\x1b[32m"; // Green
+const SEPARATOR_COLOR: &str = "\x1b[36m"; // Cyan
+const RESET: &str = "\x1b[0m";
+
+// With --json every event is one JSON object on its own line (JSON Lines), so scripts don't have to parse grep's format:
+// {"type":"begin","data":{"path":"poem.txt"}}
+// {"type":"match","data":{"path":"poem.txt","line_number":2,"absolute_offset":14,"line":"are you nobody too?",
+//   "submatches":[{"match":"you","start":4,"end":7}]}}
+// {"type":"context","data":{"path":"poem.txt","line_number":3,"absolute_offset":34,"line":"then there's a pair of us"}}
+// {"type":"end","data":{"path":"poem.txt","binary":false,"stats":{"matched_lines":1,"matches":1}}}
+// {"type":"summary","data":{"stats":{"files":1,"files_with_matches":1,"matched_lines":1,"matches":1}}}
+// Offsets are in bytes, absolute_offset is where the line starts in the file and start and end are inside the line.
+
+// What was found so far, for the end and summary events.
+#[derive(Debug, Default)]
+struct Stats {
+    files: usize,
+    files_with_matches: usize,
+    matched_lines: usize,
+    matches: usize,
+}
+
+/// Prints the results of a search the way grep does, following the options in Config.
+///
+/// The printer remembers the last line it printed, so when context is on it can
+/// separate the groups of lines that aren't next to each other with "--".
+pub struct Printer<'c, W: Write> {
+    config: &'c Config,
+    out: W,
+    show_path: bool,
+    color: bool,
+    name: String, // The name of the file being searched, or "(standard input)".
+    last_line: Option<usize>, // The last line number printed for the current file.
+    printed_any: bool, // Whether any line was printed, even from a previous file.
+    file_matches: usize, // The matches printed for the current file, only counted for --json.
+    totals: Stats,
+}
+
+impl<'c, W: Write> Printer<'c, W> {
+    /// `color` turns on the ANSI colors, run decides it from `--color` and whether stdout is a terminal.
+    pub fn new(config: &'c Config, out: W, show_path: bool, color: bool) -> Printer<'c, W> {
+        Printer {
+            config,
+            out,
+            show_path,
+            color,
+            name: String::new(),
+            last_line: None,
+            printed_any: false,
+            file_matches: 0,
+            totals: Stats::default(),
+        }
+    }
+
+    /// Must be called before printing the lines of a new file.
+    pub fn start(&mut self, name: &str) -> io::Result<()> {
+        self.name = name.to_string();
+        self.last_line = None;
+        self.file_matches = 0;
+        if self.config.mode == OutputMode::Json {
+            self.event("begin", Json::object([("path", name.into())]))?;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, kind: &str, data: Json) -> io::Result<()> {
+        let event = Json::object([("type", kind.into()), ("data", data)]);
+        writeln!(self.out, "{event}")
+    }
+
+    fn json_line(&mut self, line: &Line) -> io::Result<()> {
+        match line {
+            Line::Match(m) => {
+                let submatches: Vec<Json> = m
+                    .spans
+                    .iter()
+                    .filter(|(start, end)| start < end)
+                    .map(|&(start, end)| {
+                        Json::object([
+                            ("match", m.line[start..end].into()),
+                            ("start", start.into()),
+                            ("end", end.into()),
+                        ])
+                    })
+                    .collect();
+                self.file_matches += submatches.len();
+                let data = Json::object([
+                    ("path", self.name.as_str().into()),
+                    ("line_number", m.line_number.into()),
+                    ("absolute_offset", m.offset.into()),
+                    ("line", m.line.into()),
+                    ("submatches", submatches.into()),
+                ]);
+                self.event("match", data)
+            }
+            Line::Context {
+                line_number,
+                offset,
+                line,
+            } => {
+                let data = Json::object([
+                    ("path", self.name.as_str().into()),
+                    ("line_number", (*line_number).into()),
+                    ("absolute_offset", (*offset).into()),
+                    ("line", (*line).into()),
+                ]);
+                self.event("context", data)
+            }
+        }
+    }
+
+    fn has_context(&self) -> bool {
+        self.config.before_context > 0 || self.config.after_context > 0
+    }
+
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.color {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    // The prefix is the same for every output mode: "path:" and then "line_number:" if they were asked for.
+    // Context lines use '-' instead of ':' like grep does, so they are easy to tell apart from the matches.
+    fn prefix(&self, line_number: usize, separator: char) -> String {
+        let separator = self.paint(&separator.to_string(), SEPARATOR_COLOR);
+        let mut prefix = String::new();
+        if self.show_path {
+            prefix.push_str(&self.paint(&self.name, PATH_COLOR));
+            prefix.push_str(&separator);
+        }
+        if self.config.line_number {
+            prefix.push_str(&self.paint(&line_number.to_string(), LINE_NUMBER_COLOR));
+            prefix.push_str(&separator);
+        }
+        prefix
+    }
+
+    // Colors every match inside the line, the text between the matches is left as it is.
+    fn highlight(&self, line: &str, spans: &[(usize, usize)]) -> String {
+        if !self.color {
+            return line.to_string();
+        }
+        let mut highlighted = String::with_capacity(line.len());
+        let mut last = 0;
+        for &(start, end) in spans.iter().filter(|(start, end)| start < end) {
+            highlighted.push_str(&line[last..start]);
+            highlighted.push_str(&self.paint(&line[start..end], MATCH_COLOR));
+            last = end;
+        }
+        highlighted.push_str(&line[last..]);
+        highlighted
+    }
+
+    /// Prints a matching line or a context line, only the Lines, OnlyMatching and Json modes print lines.
+    pub fn line(&mut self, line: &Line) -> io::Result<()> {
+        let line_number = match line {
+            Line::Match(m) => m.line_number,
+            Line::Context { line_number, .. } => *line_number,
+        };
+        match (self.config.mode, line) {
+            (OutputMode::Lines, _) => {
+                if self.has_context()
+                    && self.printed_any
+                    && self.last_line.map(|last| last + 1) != Some(line_number)
+                {
+                    writeln!(self.out, "{}", self.paint("--", SEPARATOR_COLOR))?;
+                }
+                match line {
+                    Line::Match(m) => {
+                        let text = self.highlight(m.line, &m.spans);
+                        writeln!(self.out, "{}{}", self.prefix(line_number, ':'), text)?
+                    }
+                    Line::Context { line, .. } => {
+                        writeln!(self.out, "{}{}", self.prefix(line_number, '-'), line)?
+                    }
+                }
+            }
+            (OutputMode::OnlyMatching, Line::Match(m)) => {
+                // Empty matches (like the ones from a* ) have nothing to show.
+                for &(start, end) in m.spans.iter().filter(|(start, end)| start < end) {
+                    let prefix = self.prefix(line_number, ':');
+                    let separator = self.paint(":", SEPARATOR_COLOR);
+                    let text = self.paint(&m.line[start..end], MATCH_COLOR);
+                    writeln!(self.out, "{prefix}{}{separator}{text}", m.offset + start)?;
+                }
+            }
+            (OutputMode::Json, _) => self.json_line(line)?,
+            _ => return Ok(()),
+        }
+        self.last_line = Some(line_number);
+        self.printed_any = true;
+        Ok(())
+    }
+
+    /// Must be called after the last line of a file, with what the search found in it.
+    pub fn finish(&mut self, summary: Summary) -> io::Result<()> {
+        let name = self.paint(&self.name, PATH_COLOR);
+        let count = summary.count;
+        self.totals.files += 1;
+        self.totals.matched_lines += count;
+        self.totals.matches += self.file_matches;
+        if count > 0 {
+            self.totals.files_with_matches += 1;
+        }
+        match self.config.mode {
+            OutputMode::Count if self.show_path => {
+                let separator = self.paint(":", SEPARATOR_COLOR);
+                writeln!(self.out, "{name}{separator}{count}")
+            }
+            OutputMode::Count => writeln!(self.out, "{count}"),
+            OutputMode::FilesWithMatches if count > 0 => writeln!(self.out, "{name}"),
+            // The lines of a binary file weren't printed, grep only says that there were matches.
+            OutputMode::Lines | OutputMode::OnlyMatching if summary.binary && count > 0 => {
+                writeln!(self.out, "Binary file {} matches", self.name)
+            }
+            OutputMode::Json => {
+                let stats = Json::object([
+                    ("matched_lines", count.into()),
+                    ("matches", self.file_matches.into()),
+                ]);
+                let data = Json::object([
+                    ("path", self.name.as_str().into()),
+                    ("binary", summary.binary.into()),
+                    ("stats", stats),
+                ]);
+                self.event("end", data)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes out what is still buffered, for the modes that keep running like --watch.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Whether any line was selected in the files finished so far.
+    pub fn matched(&self) -> bool {
+        self.totals.matched_lines > 0
+    }
+
+    /// Must be called once after the last file, only --json prints something: the totals of every file.
+    pub fn summary(&mut self) -> io::Result<()> {
+        if self.config.mode != OutputMode::Json {
+            return Ok(());
+        }
+        let stats = Json::object([
+            ("files", self.totals.files.into()),
+            ("files_with_matches", self.totals.files_with_matches.into()),
+            ("matched_lines", self.totals.matched_lines.into()),
+            ("matches", self.totals.matches.into()),
+        ]);
+        self.event("summary", Json::object([("stats", stats)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stream::search_reader, Searcher};
+
+    fn print(config: &Config, show_path: bool, files: &[(&str, &str)]) -> String {
+        print_with_color(config, show_path, false, files)
+    }
+
+    fn print_with_color(
+        config: &Config,
+        show_path: bool,
+        color: bool,
+        files: &[(&str, &str)],
+    ) -> String {
+        let searcher = Searcher::build(&config.query, config.search_options()).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(config, &mut out, show_path, color);
+        for (name, contents) in files {
+            printer.start(name).unwrap();
+            let summary = search_reader(
+                &searcher,
+                contents.as_bytes(),
+                config.read_options(),
+                |line| printer.line(&line),
+            )
+            .unwrap();
+            printer.finish(summary).unwrap();
+        }
+        printer.summary().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn context_separators_and_prefixes() {
+        let config = Config {
+            query: String::from("match"),
+            line_number: true,
+            after_context: 1,
+            ..Config::default()
+        };
+        let output = print(
+            &config,
+            true,
+            &[("a", "match\nx\ny\nmatch"), ("b", "match")],
+        );
+        assert_eq!(output, "a:1:match\na-2-x\n--\na:4:match\n--\nb:1:match\n");
+    }
+
+    #[test]
+    fn count_and_files_with_matches() {
+        let mut config = Config {
+            query: String::from("us"),
+            mode: OutputMode::Count,
+            ..Config::default()
+        };
+        let files = [("poem", "us\nthem\nus"), ("other", "nothing")];
+        assert_eq!(print(&config, true, &files), "poem:2\nother:0\n");
+        config.mode = OutputMode::FilesWithMatches;
+        assert_eq!(print(&config, false, &files), "poem\n");
+    }
+
+    #[test]
+    fn binary_files() {
+        let mut config = Config {
+            query: String::from("us"),
+            ..Config::default()
+        };
+        let files = [("data.bin", "us\0them\nus"), ("poem", "us")];
+        assert_eq!(
+            print(&config, true, &files),
+            "Binary file data.bin matches\npoem:us\n"
+        );
+        config.mode = OutputMode::Count;
+        assert_eq!(print(&config, true, &files), "data.bin:2\npoem:1\n");
+    }
+
+    #[test]
+    fn json_events() {
+        let config = Config {
+            query: String::from("us"),
+            mode: OutputMode::Json,
+            after_context: 1,
+            ..Config::default()
+        };
+        let output = print(&config, false, &[("a \"b\"", "us and us\n\tthem\nnone")]);
+        let expected = [
+            r#"{"type":"begin","data":{"path":"a \"b\""}}"#,
+            r#"{"type":"match","data":{"path":"a \"b\"","line_number":1,"absolute_offset":0,"line":"us and us","submatches":[{"match":"us","start":0,"end":2},{"match":"us","start":7,"end":9}]}}"#,
+            r#"{"type":"context","data":{"path":"a \"b\"","line_number":2,"absolute_offset":10,"line":"\tthem"}}"#,
+            r#"{"type":"end","data":{"path":"a \"b\"","binary":false,"stats":{"matched_lines":1,"matches":2}}}"#,
+            r#"{"type":"summary","data":{"stats":{"files":1,"files_with_matches":1,"matched_lines":1,"matches":2}}}"#,
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn colors() {
+        let config = Config {
+            query: String::from("US"),
+            ignore_case: true,
+            line_number: true,
+            ..Config::default()
+        };
+        let output = print_with_color(&config, true, true, &[("poem", "pair of us")]);
+        assert_eq!(
+            output,
+            "\x1b[35mpoem\x1b[0m\x1b[36m:\x1b[0m\x1b[32m1\x1b[0m\x1b[36m:\x1b[0mpair of \x1b[1;31mus\x1b[0m\n"
+        );
+    }
+}
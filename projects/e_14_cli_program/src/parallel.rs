@@ -0,0 +1,281 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+};
+
+use crate::{
+    context::Line,
+    error::Warnings,
+    index::TrigramQuery,
+    inputs,
+    output::Printer,
+    pool::ThreadPool,
+    stream::{search_reader, ReadOptions, Summary},
+    Config, Input, Match, MinigrepError, OutputMode, Searcher, STDIN_NAME,
+};
+
+// A line found by a worker, it owns its text so it can be sent to the thread that prints.
+struct FoundLine {
+    line_number: usize,
+    offset: usize,
+    line: String,
+    spans: Option<Vec<(usize, usize)>>, // None for context lines.
+}
+
+// Everything a worker found in one input.
+struct FileResult {
+    name: String,
+    lines: Vec<FoundLine>,
+    outcome: io::Result<Summary>, // What the search found, or why the input couldn't be read.
+}
+
+/// Searches every input in a pool of `jobs` threads and prints the results in the same order as
+/// a search in a single thread would, one whole file at a time.
+///
+/// The workers only search, the printing is done here, so the output of two files is never mixed.
+/// A file that finishes early waits in memory until the files before it have been printed.
+pub(crate) fn search_parallel<W: Write>(
+    config: &Config,
+    searcher: Searcher,
+    printer: &mut Printer<W>,
+    paths: &[String],
+    trigrams: Option<&TrigramQuery>,
+    warnings: &Warnings,
+    jobs: usize,
+) -> Result<(), MinigrepError> {
+    let searcher = Arc::new(searcher);
+    let options = config.read_options();
+    // -c and -l only print a number or a name, so the workers don't have to keep the lines.
+    let keep_lines = matches!(
+        config.mode,
+        OutputMode::Lines | OutputMode::OnlyMatching | OutputMode::Json
+    );
+    // Set when printing fails, so the jobs still waiting in the pool don't search for nothing.
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    // With fewer inputs than jobs, the threads that would never get one aren't started.
+    // Only the first `jobs` inputs are walked before the pool exists, so the search still starts early.
+    let filter = config.filter();
+    let mut inputs = inputs(paths, &filter, trigrams, warnings);
+    let first: Vec<Input> = inputs.by_ref().take(jobs).collect();
+    let pool = ThreadPool::new(first.len().max(1))?;
+    let (sender, receiver) = mpsc::channel();
+    let mut ready = BTreeMap::new();
+    let mut next = 0;
+
+    for (index, input) in first.into_iter().chain(inputs).enumerate() {
+        let searcher = Arc::clone(&searcher);
+        let stop = Arc::clone(&cancelled);
+        let sender = sender.clone();
+        pool.execute(move || {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let name = input_name(&input);
+            let result = catch_panic(name, || search_file(&searcher, input, options, keep_lines));
+            // The receiver is only gone when printing failed, the result isn't needed then.
+            let _ = sender.send((index, result));
+        });
+
+        // Print what is already done while the walk goes on, so the first results show up early.
+        ready.extend(receiver.try_iter());
+        print_ready(printer, warnings, &mut ready, &mut next)
+            .inspect_err(|_| cancelled.store(true, Ordering::Relaxed))
+            .map_err(MinigrepError::Output)?;
+    }
+
+    // Once every job has dropped its sender the loop ends, so this one must go first.
+    drop(sender);
+    for (index, result) in &receiver {
+        ready.insert(index, result);
+        print_ready(printer, warnings, &mut ready, &mut next)
+            .inspect_err(|_| cancelled.store(true, Ordering::Relaxed))
+            .map_err(MinigrepError::Output)?;
+    }
+    Ok(())
+}
+
+// Prints the results that are next in order, and leaves the others waiting for the ones before them.
+fn print_ready<W: Write>(
+    printer: &mut Printer<W>,
+    warnings: &Warnings,
+    ready: &mut BTreeMap<usize, FileResult>,
+    next: &mut usize,
+) -> io::Result<()> {
+    while let Some(result) = ready.remove(next) {
+        *next += 1;
+        printer.start(&result.name)?;
+        for found in result.lines {
+            let line = match found.spans {
+                Some(spans) => Line::Match(Match {
+                    line_number: found.line_number,
+                    offset: found.offset,
+                    line: &found.line,
+                    spans,
+                }),
+                None => Line::Context {
+                    line_number: found.line_number,
+                    offset: found.offset,
+                    line: &found.line,
+                },
+            };
+            printer.line(&line)?;
+        }
+        // Same as when searching in one thread: the lines read before the error are printed, then the warning.
+        match result.outcome {
+            Ok(summary) => printer.finish(summary)?,
+            Err(error) => warnings.report(MinigrepError::Io {
+                path: result.name,
+                error,
+            }),
+        }
+    }
+    Ok(())
+}
+
+// The results are printed in order, so a job that panics without sending its result would stop
+// every file after it from being printed. Its slot gets an error instead, reported like a file that can't be read.
+fn catch_panic(name: String, search: impl FnOnce() -> FileResult) -> FileResult {
+    panic::catch_unwind(AssertUnwindSafe(search)).unwrap_or_else(|_| FileResult {
+        name,
+        lines: Vec::new(),
+        outcome: Err(io::Error::other("the search panicked")),
+    })
+}
+
+fn input_name(input: &Input) -> String {
+    match input {
+        Input::Stdin => String::from(STDIN_NAME),
+        Input::File(path) | Input::Unmatched(path) => path.display().to_string(),
+    }
+}
+
+fn search_file(
+    searcher: &Searcher,
+    input: Input,
+    options: ReadOptions,
+    keep_lines: bool,
+) -> FileResult {
+    let name = input_name(&input);
+    let reader: io::Result<Box<dyn BufRead>> = match input {
+        Input::Stdin => Ok(Box::new(io::stdin().lock())),
+        Input::File(path) => {
+            File::open(&path).map(|file| Box::new(BufReader::new(file)) as Box<dyn BufRead>)
+        }
+        Input::Unmatched(_) => Ok(Box::new(io::empty())),
+    };
+
+    let mut lines = Vec::new();
+    let outcome = reader.and_then(|reader| {
+        search_reader(searcher, reader, options, |line| {
+            if keep_lines {
+                lines.push(match line {
+                    Line::Match(m) => FoundLine {
+                        line_number: m.line_number,
+                        offset: m.offset,
+                        line: m.line.to_string(),
+                        spans: Some(m.spans),
+                    },
+                    Line::Context {
+                        line_number,
+                        offset,
+                        line,
+                    } => FoundLine {
+                        line_number,
+                        offset,
+                        line: line.to_string(),
+                        spans: None,
+                    },
+                });
+            }
+            Ok(())
+        })
+    });
+    FileResult {
+        name,
+        lines,
+        outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SearchOptions;
+    use std::{env, fs};
+
+    #[test]
+    fn same_output_as_one_thread() {
+        let dir = env::temp_dir().join(format!("minigrep-parallel-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..20 {
+            let contents = format!("line {i}\nmatch {i}\nother\n").repeat(i);
+            fs::write(dir.join(format!("{i:02}.txt")), contents).unwrap();
+        }
+        let config = Config {
+            query: String::from("match"),
+            after_context: 1,
+            ..Config::default()
+        };
+        let paths = vec![dir.display().to_string()];
+
+        let output = |jobs| {
+            let searcher = Searcher::build("match", SearchOptions::default()).unwrap();
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&config, &mut out, true, false);
+            let warnings = Warnings::default();
+            search_parallel(
+                &config,
+                searcher,
+                &mut printer,
+                &paths,
+                None,
+                &warnings,
+                jobs,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let expected = output(1);
+        assert!(expected.contains("--"));
+        for _ in 0..5 {
+            assert_eq!(output(4), expected);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_panicking_search_is_a_path_error() {
+        let config = Config::default();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, &mut out, true, false);
+        let warnings = Warnings::default();
+        let found = |name: &str| FileResult {
+            name: String::from(name),
+            lines: Vec::new(),
+            outcome: Ok(Summary {
+                count: 0,
+                binary: false,
+            }),
+        };
+        let mut ready = BTreeMap::new();
+        ready.insert(0, catch_panic(String::from("a.txt"), || found("a.txt")));
+        ready.insert(
+            1,
+            catch_panic(String::from("b.txt"), || panic!("a bug in the search")),
+        );
+        ready.insert(2, catch_panic(String::from("c.txt"), || found("c.txt")));
+        let mut next = 0;
+        print_ready(&mut printer, &warnings, &mut ready, &mut next).unwrap();
+        // The file after the one that panicked is still printed.
+        assert_eq!(next, 3);
+        assert!(ready.is_empty());
+        assert!(warnings.any());
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    io,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use crate::MinigrepError;
+
+// The same thread pool as the one of the web server in e_22_web_server, without the messages it prints.
+// Jobs are sent down a channel and every worker takes the next one as soon as it is free.
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+impl ThreadPool {
+    /// Creates a pool with `size` threads, or returns why the system couldn't start one of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size is zero.
+    pub fn new(size: usize) -> Result<ThreadPool, MinigrepError> {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut pool = ThreadPool {
+            workers: Vec::with_capacity(size),
+            sender: Some(sender),
+        };
+        for _ in 0..size {
+            // On an error the pool is dropped, which stops the threads that were already started.
+            let worker = Worker::new(Arc::clone(&receiver)).map_err(MinigrepError::Thread)?;
+            pool.workers.push(worker);
+        }
+        Ok(pool)
+    }
+
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // The receiving end only goes away when every worker panicked, there is nobody left to run the job then.
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(Box::new(f))
+            .expect("every worker thread has stopped");
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the channel makes every worker stop once the jobs already sent are done.
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                // A job that panicked already printed its message, there is nothing else to do with it here.
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+struct Worker {
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    // thread::spawn panics when the thread can't be started, the Builder returns the error instead.
+    fn new(receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> io::Result<Worker> {
+        let thread = thread::Builder::new().spawn(move || loop {
+            // The lock is released at the end of this statement, so other workers can wait for a job while this one runs.
+            let message = receiver.lock().unwrap().recv();
+            match message {
+                Ok(job) => job(),
+                Err(_) => break,
+            }
+        })?;
+
+        Ok(Worker {
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_every_job() {
+        let (sender, receiver) = mpsc::channel();
+        let pool = ThreadPool::new(3).unwrap();
+        for i in 0..10 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(i).unwrap());
+        }
+        drop(pool); // Waits for the jobs to finish.
+        drop(sender);
+        let mut results: Vec<i32> = receiver.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,833 @@
+use std::{error::Error, fmt};
+
+use crate::fold;
+
+// A small regular expression engine so minigrep doesn't need an external crate.
+// The pattern is parsed into a tree (Node), compiled into a list of instructions (Inst)
+// and then executed with a Pike VM, which simulates every possible path of the pattern at once.
+// This means the running time is linear in the size of the text, there is no backtracking.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub message: String,
+    pub position: usize, // The character position in the pattern where the error was found.
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "regex parse error at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl Error for RegexError {}
+
+// Patterns like a{1000}{1000} could make the program huge, so we stop compiling after this limit.
+const MAX_INSTRUCTIONS: usize = 100_000;
+const MAX_REPEAT: u32 = 1_000;
+// The parser and the compiler call themselves for every group and repetition inside another one,
+// so thousands of nested '(' would overflow the stack. Deeper patterns are refused.
+const MAX_NESTING: usize = 250;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool), // The bool is true for the negated versions like \D, \W and \S.
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn perl(kind: Perl, negated: bool) -> Class {
+        Class {
+            items: vec![ClassItem::Perl(kind, false)],
+            negated,
+        }
+    }
+
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let found = if ignore_case {
+            // Try the character as it is and also its other cases, so [a-z] matches 'Q'.
+            std::iter::once(c)
+                .chain(c.to_lowercase())
+                .chain(c.to_uppercase())
+                .any(|c| self.contains(c))
+        } else {
+            self.contains(c)
+        };
+        found != self.negated
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Perl(kind, negated) => kind.matches(c) != negated,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Look {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        match self {
+            Look::Start => pos == 0,
+            Look::End => pos == text.len(),
+            Look::WordBoundary | Look::NotWordBoundary => {
+                let before = text[..pos].chars().next_back().is_some_and(is_word_char);
+                let after = text[pos..].chars().next().is_some_and(is_word_char);
+                (before != after) == (self == Look::WordBoundary)
+            }
+        }
+    }
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>), // None is a non-capturing group: (?:...)
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    depth: usize, // How many groups and repetitions contain the node being parsed.
+}
+
+impl Parser {
+    fn error<T>(&self, message: &str) -> Result<T, RegexError> {
+        Err(RegexError {
+            message: message.to_string(),
+            position: self.pos,
+        })
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), RegexError> {
+        if depth > MAX_NESTING {
+            return self.error("pattern is nested too deeply");
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(&mut self) -> Result<Node, RegexError> {
+        let node = self.parse_alternation()?;
+        if self.pos < self.chars.len() {
+            // The only way to stop early is an unmatched closing parenthesis.
+            return self.error("unmatched ')'");
+        }
+        Ok(node)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        match c {
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return self.error("only non-capturing groups (?:...) are supported");
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                self.depth += 1;
+                self.check_depth(self.depth)?;
+                let node = self.parse_alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return self.error("unclosed group");
+                }
+                Ok(Node::Group(Box::new(node), index))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Look(Look::Start)),
+            '$' => Ok(Node::Look(Look::End)),
+            '\\' => self.parse_escape(),
+            '*' | '+' | '?' => {
+                self.pos -= 1;
+                self.error("nothing to repeat")
+            }
+            c => Ok(Node::Literal(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("pattern ends with a trailing backslash"),
+        };
+        self.pos += 1;
+        Ok(match c {
+            'd' => Node::Class(Class::perl(Perl::Digit, false)),
+            'D' => Node::Class(Class::perl(Perl::Digit, true)),
+            'w' => Node::Class(Class::perl(Perl::Word, false)),
+            'W' => Node::Class(Class::perl(Perl::Word, true)),
+            's' => Node::Class(Class::perl(Perl::Space, false)),
+            'S' => Node::Class(Class::perl(Perl::Space, true)),
+            'b' => Node::Look(Look::WordBoundary),
+            'B' => Node::Look(Look::NotWordBoundary),
+            c => Node::Literal(self.escaped_char(c)?),
+        })
+    }
+
+    fn escaped_char(&self, c: char) -> Result<char, RegexError> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            c if c.is_ascii_alphanumeric() => self.error("unknown escape sequence"),
+            // Any other escaped character is taken literally, like \. or \\ or \(
+            c => Ok(c),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return self.error("unclosed character class"),
+            };
+            self.pos += 1;
+            // A ']' right after the opening bracket is a literal, like in []abc]
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                match self.parse_class_escape()? {
+                    ClassItem::Range(lo, _) => lo,
+                    perl => {
+                        items.push(perl);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+
+            // A '-' followed by ']' is a literal dash, otherwise it makes a range.
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                let hi = match self.peek() {
+                    Some('\\') => {
+                        self.pos += 1;
+                        match self.parse_class_escape()? {
+                            ClassItem::Range(hi, _) => hi,
+                            _ => return self.error("invalid range end in character class"),
+                        }
+                    }
+                    Some(c) => {
+                        self.pos += 1;
+                        c
+                    }
+                    None => return self.error("unclosed character class"),
+                };
+                if lo > hi {
+                    return self.error("invalid range in character class");
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Node::Class(Class { items, negated }))
+    }
+
+    fn parse_class_escape(&mut self) -> Result<ClassItem, RegexError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("unclosed character class"),
+        };
+        self.pos += 1;
+        Ok(match c {
+            'd' => ClassItem::Perl(Perl::Digit, false),
+            'D' => ClassItem::Perl(Perl::Digit, true),
+            'w' => ClassItem::Perl(Perl::Word, false),
+            'W' => ClassItem::Perl(Perl::Word, true),
+            's' => ClassItem::Perl(Perl::Space, false),
+            'S' => ClassItem::Perl(Perl::Space, true),
+            c => {
+                let c = self.escaped_char(c)?;
+                ClassItem::Range(c, c)
+            }
+        })
+    }
+
+    fn parse_repeat(&mut self, mut atom: Node) -> Result<Node, RegexError> {
+        // Like a***, every repetition wraps the previous one.
+        let mut depth = self.depth;
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    // Not a valid counted repetition, so the '{' is parsed as a literal later.
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+            let greedy = !self.eat('?');
+            depth += 1;
+            self.check_depth(depth)?;
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // Parses {n}, {n,} and {n,m}, returns None without moving if the braces aren't a repetition.
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        let close = match self.chars[start..].iter().position(|&c| c == '}') {
+            Some(offset) => start + offset,
+            None => return Ok(None),
+        };
+        let inner: String = self.chars[start + 1..close].iter().collect();
+        let parse = |s: &str| s.parse::<u32>().ok();
+        let bounds = match inner.split_once(',') {
+            None => parse(&inner).map(|n| (n, Some(n))),
+            Some((min, "")) => parse(min).map(|n| (n, None)),
+            Some((min, max)) => parse(min).zip(parse(max)).map(|(n, m)| (n, Some(m))),
+        };
+        let (min, max) = match bounds {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+        if max.is_some_and(|max| max < min) {
+            return self.error("invalid repetition range, min is greater than max");
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return self.error("repetition count is too large");
+        }
+        self.pos = close + 1;
+        Ok(Some((min, max)))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Look),
+    Save(usize),
+    Split(usize, usize), // Try the first branch before the second one.
+    Jmp(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.program.len() >= MAX_INSTRUCTIONS {
+            return Err(RegexError {
+                message: String::from("pattern is too large"),
+                position: 0,
+            });
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()))?;
+            }
+            Node::Look(look) => {
+                self.push(Inst::Assert(*look))?;
+            }
+            Node::Group(node, Some(index)) => {
+                self.push(Inst::Save(index * 2))?;
+                self.compile(node)?;
+                self.push(Inst::Save(index * 2 + 1))?;
+            }
+            Node::Group(node, None) => self.compile(node)?,
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                // a|b|c becomes: split L1, L2; L1: a; jmp end; L2: split L3, L4; L3: b; jmp end; L4: c; end:
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i == branches.len() - 1 {
+                        self.compile(branch)?;
+                    } else {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(branch)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        self.program[split] = Inst::Split(split + 1, self.program.len());
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                // The order of the Split branches is what makes a repetition greedy or lazy.
+                let split = |body: usize, out: usize| {
+                    if *greedy {
+                        Inst::Split(body, out)
+                    } else {
+                        Inst::Split(out, body)
+                    }
+                };
+                match max {
+                    None => {
+                        let start = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        self.push(Inst::Jmp(start))?;
+                        self.program[start] = split(start + 1, self.program.len());
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.program.len();
+                        for start in splits {
+                            self.program[start] = split(start + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Thread {
+    pc: usize,
+    slots: Vec<Option<usize>>,
+}
+
+// The list of threads that are alive at one position of the text.
+// seen makes sure every instruction is only added once, which is what keeps the VM linear.
+struct Threads {
+    threads: Vec<Thread>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            threads: Vec::new(),
+            seen: vec![false; size],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.seen.iter_mut().for_each(|seen| *seen = false);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    slots: usize,
+    ignore_case: bool,
+}
+
+impl Regex {
+    /// Compile a pattern into a Regex.
+    ///
+    /// Supports literals, `.`, character classes like `[a-z]` and `[^0-9]`, `\d`, `\w`, `\s`
+    /// (and their negations), the anchors `^`, `$` and `\b`, alternation with `|`, groups and
+    /// the repetitions `*`, `+`, `?` and `{n,m}` (add a `?` after them to make them lazy).
+    pub fn build(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+            depth: 0,
+        };
+        let node = parser.parse()?;
+
+        // The whole match is stored as group 0, so we wrap the program in Save instructions.
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        compiler.push(Inst::Save(0))?;
+        compiler.compile(&node)?;
+        compiler.push(Inst::Save(1))?;
+        compiler.push(Inst::Match)?;
+
+        Ok(Regex {
+            program: compiler.program,
+            slots: (parser.groups + 1) * 2,
+            ignore_case,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the byte range of the leftmost match.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    /// Like find, but the search starts at the byte offset `start`.
+    /// Anchors still look at the whole text, so `^` won't match at `start` unless it is 0.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let slots = self.exec(text, start)?;
+        Some((slots[0]?, slots[1]?))
+    }
+
+    /// Like find_at, but also returns where every capture group matched.
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let slots = self.exec(text, start)?;
+        Some(Captures { slots })
+    }
+
+    /// Returns an iterator over all the non-overlapping matches in the text.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            text,
+            pos: 0,
+        }
+    }
+
+    fn char_eq(&self, expected: char, c: char) -> bool {
+        expected == c || (self.ignore_case && fold::chars_eq(expected, c))
+    }
+
+    // Follows the instructions that don't consume any character (jumps, splits, saves and assertions)
+    // and adds the threads that end up waiting for a character to the list.
+    fn add_thread(
+        &self,
+        list: &mut Threads,
+        pc: usize,
+        slots: Vec<Option<usize>>,
+        text: &str,
+        pos: usize,
+    ) {
+        // We use our own stack instead of recursion so long patterns can't overflow the real one.
+        // The second branch of a split is pushed first so the first branch is explored first.
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if list.seen[pc] {
+                continue;
+            }
+            list.seen[pc] = true;
+            match self.program[pc] {
+                Inst::Jmp(target) => stack.push((target, slots)),
+                Inst::Split(first, second) => {
+                    stack.push((second, slots.clone()));
+                    stack.push((first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Assert(look) => {
+                    if look.holds(text, pos) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => list.threads.push(Thread { pc, slots }),
+            }
+        }
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            // Starting a new thread at every position is what makes the search unanchored.
+            // It goes last in the list because a match that starts earlier always wins.
+            if matched.is_none() {
+                self.add_thread(&mut current, 0, vec![None; self.slots], text, pos);
+            }
+            if current.threads.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let c = text[pos..].chars().next();
+            let next_pos = pos + c.map_or(0, char::len_utf8);
+            for thread in current.threads.drain(..) {
+                let Thread { pc, slots } = thread;
+                let consumed = match (&self.program[pc], c) {
+                    (Inst::Match, _) => {
+                        // Threads after this one have lower priority, so we can forget them.
+                        matched = Some(slots);
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => self.char_eq(*expected, c),
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Class(class), Some(c)) => class.matches(c, self.ignore_case),
+                    _ => false,
+                };
+                if consumed {
+                    self.add_thread(&mut next, pc + 1, slots, text, next_pos);
+                }
+            }
+
+            if c.is_none() {
+                break;
+            }
+            pos = next_pos;
+            current.clear();
+            std::mem::swap(&mut current, &mut next);
+        }
+        matched
+    }
+}
+
+/// Where a match and its groups are in the text, see Regex::captures_at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures {
+    slots: Vec<Option<usize>>, // Group i starts at slots[2 * i] and ends at slots[2 * i + 1].
+}
+
+impl Captures {
+    /// Returns the byte range of a group, 0 is the whole match and the groups are numbered by their '(' from 1.
+    /// Returns None for a group that doesn't exist or didn't take part in the match, like the b in (a)|(b).
+    pub fn get(&self, group: usize) -> Option<(usize, usize)> {
+        let start = (*self.slots.get(group * 2)?)?;
+        let end = (*self.slots.get(group * 2 + 1)?)?;
+        Some((start, end))
+    }
+}
+
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    pos: usize,
+}
+
+impl Iterator for Matches<'_, '_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.pos > self.text.len() {
+            return None;
+        }
+        let (start, end) = self.regex.find_at(self.text, self.pos)?;
+        self.pos = if start == end {
+            // An empty match would be found again at the same place, so we skip one character.
+            end + self.text[end..].chars().next().map_or(1, char::len_utf8)
+        } else {
+            end
+        };
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::build(pattern, false).unwrap().find(text)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert_eq!(find("duct", "safe, fast, productive."), Some((15, 19)));
+        assert_eq!(find("[0-9]+", "error 404 found"), Some((6, 9)));
+        assert_eq!(find(r"\d+\s\w+", "code 42 answer"), Some((5, 14)));
+        assert_eq!(find("[^a-z ]", "abc def!"), Some((7, 8)));
+        assert_eq!(find(r"a\.b", "axb a.b"), Some((4, 7)));
+    }
+
+    #[test]
+    fn anchors() {
+        let re = Regex::build(r"^ERROR \d+", false).unwrap();
+        assert!(re.is_match("ERROR 500 internal"));
+        assert!(!re.is_match("got ERROR 500"));
+        assert_eq!(find("bog!$", "To an admiring bog!"), Some((15, 19)));
+        assert_eq!(find(r"\bus\b", "Then there's a pair of us"), Some((23, 25)));
+        assert_eq!(find(r"\bus\b", "trust"), None);
+    }
+
+    #[test]
+    fn alternation_groups_and_repetition() {
+        assert_eq!(find("frog|bog", "like a frog"), Some((7, 11)));
+        assert_eq!(find("(ab)+c", "xxababc"), Some((2, 7)));
+        assert_eq!(find("(?:no|some)body", "I'm nobody!"), Some((4, 10)));
+        assert_eq!(find("a{2,3}", "caaaat"), Some((1, 4)));
+        assert_eq!(find("a{2}", "cat"), None);
+        assert_eq!(find("<.+?>", "<a><b>"), Some((0, 3)));
+        assert_eq!(find("<.+>", "<a><b>"), Some((0, 6)));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let re = Regex::build("ru[s]t", true).unwrap();
+        assert_eq!(re.find("Trust me."), Some((1, 5)));
+        assert!(re.is_match("RUST"));
+    }
+
+    #[test]
+    fn capture_groups() {
+        let re = Regex::build(r"(\w+)@(\w+)(?:\.(com)|\.(org))", false).unwrap();
+        let captures = re.captures_at("mail: ferris@rust.org", 0).unwrap();
+        assert_eq!(captures.get(0), Some((6, 21)));
+        assert_eq!(captures.get(1), Some((6, 12)));
+        assert_eq!(captures.get(2), Some((13, 17)));
+        assert_eq!(captures.get(3), None);
+        assert_eq!(captures.get(4), Some((18, 21)));
+        assert_eq!(captures.get(5), None);
+    }
+
+    #[test]
+    fn find_iter_handles_empty_matches() {
+        let re = Regex::build("a*", false).unwrap();
+        let matches: Vec<_> = re.find_iter("baa").collect();
+        assert_eq!(matches, vec![(0, 0), (1, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Regex::build("(abc", false).is_err());
+        assert!(Regex::build("abc)", false).is_err());
+        assert!(Regex::build("*a", false).is_err());
+        assert!(Regex::build("[z-a]", false).is_err());
+        assert!(Regex::build(r"abc\", false).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = "(".repeat(3000) + "a" + &")".repeat(3000);
+        let err = Regex::build(&deep, false).unwrap_err();
+        assert_eq!(err.message, "pattern is nested too deeply");
+        let stacked = String::from("a") + &"*".repeat(3000);
+        assert!(Regex::build(&stacked, false).is_err());
+        // A reasonable nesting still works.
+        let nested = "(".repeat(50) + "a" + &")".repeat(50);
+        assert!(Regex::build(&nested, false).unwrap().is_match("a"));
+    }
+}
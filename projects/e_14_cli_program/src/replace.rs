@@ -0,0 +1,419 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    process,
+};
+
+use crate::{
+    error::Warnings, index::TrigramQuery, inputs, lines_with_offsets, regex::Captures, Config,
+    Input, Matcher, MinigrepError, Searcher,
+};
+
+// The lines kept around every change in the diff, the same as `diff -u`.
+const DIFF_CONTEXT: usize = 3;
+
+/// A match that was replaced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub line_number: usize,
+    pub offset: usize, // The byte offset of the match in the original contents.
+    pub original: String,
+    pub replacement: String,
+}
+
+/// The contents after the replacements, and every replacement that was made.
+#[derive(Debug, PartialEq)]
+pub struct Replaced {
+    pub contents: String,
+    pub edits: Vec<Edit>,
+}
+
+/// Replaces every match of the searcher in the contents, the rest of the contents (line endings included) stays the same.
+///
+/// In `replacement`, `$0` is the whole match, `$1`, `$2`... are the groups of a regex and `$$` is a '$'.
+/// Use `${1}` when the group is followed by a digit. A group that doesn't exist or didn't match is replaced by nothing.
+pub fn replace(searcher: &Searcher, contents: &str, replacement: &str) -> Replaced {
+    let mut replaced = String::with_capacity(contents.len());
+    let mut edits = Vec::new();
+    let mut last = 0;
+    for (index, (offset, line)) in lines_with_offsets(contents).enumerate() {
+        // With -v the selected lines don't have any match, so nothing is replaced.
+        let Some(spans) = searcher.matches(line) else {
+            continue;
+        };
+        let mut previous_end = None;
+        for (start, end) in spans {
+            // Like sed, an empty match right after a match isn't replaced, so a* on "baa" gives "XbX" and not "XbXX".
+            if start == end && previous_end == Some(start) {
+                continue;
+            }
+            previous_end = Some(end);
+            // The match of find_iter is also the leftmost one from its own start, so captures_at finds the same one.
+            let captures = match searcher.matcher() {
+                Matcher::Regex(regex) => regex.captures_at(line, start),
+                _ => None,
+            };
+            let text = expand(replacement, line, (start, end), captures.as_ref());
+            replaced.push_str(&contents[last..offset + start]);
+            replaced.push_str(&text);
+            last = offset + end;
+            edits.push(Edit {
+                line_number: index + 1,
+                offset: offset + start,
+                original: line[start..end].to_string(),
+                replacement: text,
+            });
+        }
+    }
+    replaced.push_str(&contents[last..]);
+    Replaced {
+        contents: replaced,
+        edits,
+    }
+}
+
+// Without captures (a literal query) only $0 has a value.
+fn group<'l>(
+    line: &'l str,
+    span: (usize, usize),
+    captures: Option<&Captures>,
+    index: usize,
+) -> &'l str {
+    let range = match captures {
+        Some(captures) => captures.get(index),
+        None if index == 0 => Some(span),
+        None => None,
+    };
+    range.map_or("", |(start, end)| &line[start..end])
+}
+
+fn expand(template: &str, line: &str, span: (usize, usize), captures: Option<&Captures>) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let (digits, remaining) = match after.strip_prefix('{') {
+            Some(inner) => match inner.find('}') {
+                Some(close) => (&inner[..close], &inner[close + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let length = after.bytes().take_while(u8::is_ascii_digit).count();
+                (&after[..length], &after[length..])
+            }
+        };
+        // A '$' that isn't followed by a group number is kept as it is.
+        match digits.parse::<usize>() {
+            Ok(index) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                expanded.push_str(group(line, span, captures, index));
+                rest = remaining;
+            }
+            _ => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Returns a unified diff (the format of `diff -u` and `patch`) from the contents to the contents with the edits.
+/// The edits must come from `replace` on the same contents.
+///
+/// The lines keep their own line ending ("\r\n" too), and a last line without one is followed by
+/// `\ No newline at end of file` like diff does, so the diff applies with `patch`.
+pub fn unified_diff(name: &str, contents: &str, edits: &[Edit]) -> String {
+    let lines: Vec<(usize, &str)> = lines_with_offsets(contents).collect();
+    // What lines_with_offsets left out after the line: "\n", "\r\n", or nothing for the last line.
+    let ending = |index: usize| {
+        let (offset, line) = lines[index];
+        let next = lines
+            .get(index + 1)
+            .map_or(contents.len(), |&(next, _)| next);
+        &contents[offset + line.len()..next]
+    };
+    let push_line = |body: &mut String, prefix: char, line: &str, ending: &str| {
+        body.push(prefix);
+        body.push_str(line);
+        match ending {
+            "" => body.push_str("\n\\ No newline at end of file\n"),
+            ending => body.push_str(ending),
+        }
+    };
+
+    // The new text of every line with an edit, a replacement with a '\n' turns one line into several.
+    let mut changed: Vec<(usize, String)> = Vec::new();
+    let mut edits = edits.iter().peekable();
+    while let Some(first) = edits.peek() {
+        let index = first.line_number - 1;
+        let (offset, line) = lines[index];
+        let mut new_line = String::new();
+        let mut last = 0;
+        while let Some(edit) = edits.next_if(|edit| edit.line_number == index + 1) {
+            let start = edit.offset - offset;
+            new_line.push_str(&line[last..start]);
+            new_line.push_str(&edit.replacement);
+            last = start + edit.original.len();
+        }
+        new_line.push_str(&line[last..]);
+        changed.push((index, new_line));
+    }
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {name}\n+++ {name}\n");
+    // How many lines the hunks so far added (or removed when negative), it moves the start of the next ones.
+    let mut added: isize = 0;
+    let mut first = 0;
+    while first < changed.len() {
+        // Changes whose context touches or overlaps go in the same hunk.
+        let mut last = first;
+        while last + 1 < changed.len()
+            && changed[last + 1].0 - changed[last].0 <= 2 * DIFF_CONTEXT + 1
+        {
+            last += 1;
+        }
+        let start = changed[first].0.saturating_sub(DIFF_CONTEXT);
+        let end = (changed[last].0 + DIFF_CONTEXT).min(lines.len() - 1);
+
+        let mut body = String::new();
+        let mut new_count = 0;
+        let mut next = first;
+        for (index, &(_, line)) in lines.iter().enumerate().take(end + 1).skip(start) {
+            if next <= last && changed[next].0 == index {
+                push_line(&mut body, '-', line, ending(index));
+                // The '\n' of the replacement end the new lines, the last one gets the ending of the old line.
+                let mut new_lines = changed[next].1.split('\n').peekable();
+                while let Some(new_line) = new_lines.next() {
+                    let new_ending = if new_lines.peek().is_some() {
+                        "\n"
+                    } else {
+                        ending(index)
+                    };
+                    push_line(&mut body, '+', new_line, new_ending);
+                    new_count += 1;
+                }
+                next += 1;
+            } else {
+                push_line(&mut body, ' ', line, ending(index));
+                new_count += 1;
+            }
+        }
+        let old_count = end - start + 1;
+        let new_start = (start + 1) as isize + added;
+        diff.push_str(&format!(
+            "@@ -{},{old_count} +{new_start},{new_count} @@\n{body}",
+            start + 1
+        ));
+        added += new_count as isize - old_count as isize;
+        first = last + 1;
+    }
+    diff
+}
+
+/// The --replace mode of run: prints a diff of every file that would change, or with --write changes the files.
+///
+/// Standard input can't be changed in place, with --write its replaced contents are printed instead.
+/// Binary files and files that aren't UTF-8 are skipped, they couldn't be written back the way they were.
+pub(crate) fn run_replace<W: Write>(
+    config: &Config,
+    searcher: &Searcher,
+    paths: &[String],
+    trigrams: Option<&TrigramQuery>,
+    warnings: &Warnings,
+    replacement: &str,
+    out: &mut W,
+) -> io::Result<bool> {
+    // Whether something was replaced, it decides the exit code like a match does for a search.
+    let mut replaced_any = false;
+    for input in inputs(paths, &config.filter(), trigrams, warnings) {
+        let (path, bytes) = match input {
+            Input::Stdin => {
+                let mut bytes = Vec::new();
+                if let Err(error) = io::stdin().read_to_end(&mut bytes) {
+                    let path = String::from(crate::STDIN_NAME);
+                    warnings.report(MinigrepError::Io { path, error });
+                    continue;
+                }
+                (None, bytes)
+            }
+            // The index knows there is nothing to replace.
+            Input::Unmatched(_) => continue,
+            Input::File(path) => match fs::read(&path) {
+                Ok(bytes) => (Some(path), bytes),
+                Err(error) => {
+                    let path = path.display().to_string();
+                    warnings.report(MinigrepError::Io { path, error });
+                    continue;
+                }
+            },
+        };
+        let name = path
+            .as_ref()
+            .map_or(String::from(crate::STDIN_NAME), |path| {
+                path.display().to_string()
+            });
+        // They couldn't be written back the way they were, so they are skipped, but not silently:
+        // a replacement that wasn't made is worth knowing about.
+        let contents = match String::from_utf8(bytes) {
+            Ok(contents) if !contents.contains('\0') => contents,
+            _ => {
+                let error = io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "binary or not UTF-8, nothing was replaced",
+                );
+                warnings.report(MinigrepError::Io { path: name, error });
+                continue;
+            }
+        };
+
+        let replaced = replace(searcher, &contents, replacement);
+        replaced_any |= !replaced.edits.is_empty();
+        match (path, config.write) {
+            (None, true) => out.write_all(replaced.contents.as_bytes())?,
+            _ if replaced.edits.is_empty() => {}
+            (_, false) => {
+                out.write_all(unified_diff(&name, &contents, &replaced.edits).as_bytes())?
+            }
+            (Some(path), true) => match write_atomically(&path, replaced.contents.as_bytes()) {
+                Ok(()) => writeln!(out, "{name}: {} replaced", replaced.edits.len())?,
+                Err(error) => warnings.report(MinigrepError::Io { path: name, error }),
+            },
+        }
+    }
+    out.flush()?;
+    Ok(replaced_any)
+}
+
+// The new contents go to a temporary file in the same directory, which is then renamed over the original.
+// A rename replaces the file in one step, so if anything fails the original is still there, never half written.
+// A new file gets the default permissions, an existing one keeps its own.
+// A symbolic link is followed first, the rename would replace the link itself and leave its target unchanged.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = &match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.minigrep-{}.tmp", process::id()));
+    let result = (|| {
+        // create_new fails instead of overwriting a file that happens to have the same name.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        match fs::metadata(path) {
+            Ok(metadata) => fs::set_permissions(&temp, metadata.permissions())?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SearchOptions;
+
+    fn regex() -> SearchOptions {
+        SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        }
+    }
+
+    #[test]
+    fn literal_replace_keeps_line_endings() {
+        let searcher = Searcher::build("us", SearchOptions::default()).unwrap();
+        let contents = "pair of us\r\nnothing\nus and us";
+        let replaced = replace(&searcher, contents, "[$0]");
+        assert_eq!(replaced.contents, "pair of [us]\r\nnothing\n[us] and [us]");
+        assert_eq!(replaced.edits.len(), 3);
+        assert_eq!(
+            replaced.edits[0],
+            Edit {
+                line_number: 1,
+                offset: 8,
+                original: String::from("us"),
+                replacement: String::from("[us]")
+            }
+        );
+        assert_eq!(replaced.edits[2].offset, 27);
+    }
+
+    #[test]
+    fn capture_groups() {
+        let searcher = Searcher::build(r"(\w+)@(\w+)\.com", regex()).unwrap();
+        let replaced = replace(&searcher, "mail ferris@rust.com", "$2 ${1}1 $3 $$1 $x");
+        assert_eq!(replaced.contents, "mail rust ferris1  $1 $x");
+
+        let searcher = Searcher::build("a*", regex()).unwrap();
+        assert_eq!(replace(&searcher, "baa", "X").contents, "XbX");
+    }
+
+    #[test]
+    fn diff() {
+        let contents = "1\n2\n3\n4\nfoo\n6\n7\n8\n9\n10\n11\n12\nfoo\n14\n";
+        let searcher = Searcher::build("foo", SearchOptions::default()).unwrap();
+        let replaced = replace(&searcher, contents, "bar\nbaz");
+        let diff = unified_diff("file.txt", contents, &replaced.edits);
+        assert_eq!(
+            diff,
+            "--- file.txt\n+++ file.txt\n\
+             @@ -2,7 +2,8 @@\n 2\n 3\n 4\n-foo\n+bar\n+baz\n 6\n 7\n 8\n\
+             @@ -10,5 +11,6 @@\n 10\n 11\n 12\n-foo\n+bar\n+baz\n 14\n"
+        );
+    }
+
+    #[test]
+    fn diff_keeps_line_endings() {
+        let contents = "foo\r\nkeep\r\nfoo";
+        let searcher = Searcher::build("foo", SearchOptions::default()).unwrap();
+        let replaced = replace(&searcher, contents, "bar");
+        let diff = unified_diff("file.txt", contents, &replaced.edits);
+        assert_eq!(
+            diff,
+            "--- file.txt\n+++ file.txt\n\
+             @@ -1,3 +1,3 @@\n-foo\r\n+bar\r\n keep\r\n\
+             -foo\n\\ No newline at end of file\n+bar\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_follows_symlinks() {
+        let dir = std::env::temp_dir().join(format!("minigrep-replace-link-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::write(dir.join("real/notes.txt"), "old").unwrap();
+        std::os::unix::fs::symlink(dir.join("real/notes.txt"), dir.join("link.txt")).unwrap();
+
+        write_atomically(&dir.join("link.txt"), b"new").unwrap();
+        let link = fs::symlink_metadata(dir.join("link.txt")).unwrap();
+        assert!(link.file_type().is_symlink());
+        assert_eq!(
+            fs::read_to_string(dir.join("real/notes.txt")).unwrap(),
+            "new"
+        );
+        // The temporary file was next to the target, and it is gone.
+        assert_eq!(fs::read_dir(dir.join("real")).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
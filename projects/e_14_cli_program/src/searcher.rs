@@ -0,0 +1,282 @@
+use crate::{
+    aho_corasick::AhoCorasick,
+    fold,
+    fuzzy::{Fuzzy, FuzzyMatch},
+    regex::{is_word_char, Regex, RegexError},
+};
+
+/// The options that change what counts as a matching line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchOptions {
+    pub ignore_case: bool,
+    pub regex: bool,
+    pub invert_match: bool,   // Select the lines that don't match (-v).
+    pub word_regexp: bool,    // Only matches that are whole words count (-w).
+    pub line_regexp: bool,    // Only matches that are the whole line count (-x).
+    pub fuzzy: Option<usize>, // How many edits a match can be away from the query (--fuzzy).
+}
+
+/// A query that is ready to be searched for.
+pub enum Matcher {
+    Literal(String),
+    CaseInsensitive(String), // The query is stored already case folded, see fold::fold.
+    Regex(Regex),
+    Multi(AhoCorasick), // Several literal patterns (-e and -f), all searched in the same pass.
+    Fuzzy(Vec<Fuzzy>),  // One for every query.
+}
+
+impl Matcher {
+    /// Returns the matches of a Fuzzy matcher with their distances, and nothing for the other matchers.
+    pub fn fuzzy_matches(&self, line: &str) -> Vec<FuzzyMatch> {
+        let Matcher::Fuzzy(queries) = self else {
+            return Vec::new();
+        };
+        if let [query] = queries.as_slice() {
+            return query.find_iter(line);
+        }
+        // The matches of different queries can overlap, the leftmost one wins and then the closest one.
+        let mut found: Vec<FuzzyMatch> = queries.iter().flat_map(|q| q.find_iter(line)).collect();
+        found.sort_by_key(|m| (m.start, m.distance));
+        let mut matches: Vec<FuzzyMatch> = Vec::with_capacity(found.len());
+        for m in found {
+            if matches.last().is_none_or(|last| last.end <= m.start) {
+                matches.push(m);
+            }
+        }
+        matches
+    }
+
+    /// Returns the byte range of every non-overlapping match in the line.
+    pub fn find_iter(&self, line: &str) -> Vec<(usize, usize)> {
+        match self {
+            Matcher::Literal(query) => line
+                .match_indices(query.as_str())
+                .map(|(start, found)| (start, start + found.len()))
+                .collect(),
+            Matcher::CaseInsensitive(query) => fold::find_folded(query, line),
+            Matcher::Regex(regex) => regex.find_iter(line).collect(),
+            Matcher::Multi(patterns) => patterns
+                .find_iter(line)
+                .into_iter()
+                .map(|m| (m.start, m.end))
+                .collect(),
+            Matcher::Fuzzy(_) => self
+                .fuzzy_matches(line)
+                .into_iter()
+                .map(|m| (m.start, m.end))
+                .collect(),
+        }
+    }
+}
+
+/// A Matcher together with the SearchOptions that decide which lines are selected.
+/// Every search function in minigrep is built on top of this type.
+pub struct Searcher {
+    matcher: Matcher,
+    options: SearchOptions,
+}
+
+impl Searcher {
+    /// Builds the matcher for the query, this only fails when `options.regex` is on and the pattern is invalid.
+    pub fn build(query: &str, options: SearchOptions) -> Result<Searcher, RegexError> {
+        Searcher::build_many(&[query], options)
+    }
+
+    /// Builds one matcher for all the queries, a line is selected when any of them matches.
+    pub fn build_many<S: AsRef<str>>(
+        queries: &[S],
+        options: SearchOptions,
+    ) -> Result<Searcher, RegexError> {
+        if let Some(max_distance) = options.fuzzy {
+            let queries = queries
+                .iter()
+                .map(|query| Fuzzy::build(query.as_ref(), max_distance, options.ignore_case))
+                .collect();
+            let matcher = Matcher::Fuzzy(queries);
+            return Ok(Searcher { matcher, options });
+        }
+
+        let joined;
+        let query = match queries {
+            [query] => query.as_ref(),
+            // Like grep, no patterns at all (an empty -f file) means nothing matches.
+            _ if !options.regex || queries.is_empty() => {
+                let matcher = Matcher::Multi(AhoCorasick::build(queries, options.ignore_case));
+                return Ok(Searcher { matcher, options });
+            }
+            // Several regexes become one alternation, every part in its own group so a|b and c stays (?:a|b)|(?:c).
+            _ => {
+                joined = queries
+                    .iter()
+                    .map(|query| format!("(?:{})", query.as_ref()))
+                    .collect::<Vec<_>>()
+                    .join("|");
+                &joined
+            }
+        };
+
+        let matcher = if options.regex {
+            // With -x the regex has to match the whole line, not only a part of it that starts at 0.
+            // Wrapping it is the only way to get that with alternations like a|ab on the line "ab".
+            let pattern = if options.line_regexp {
+                format!("^(?:{query})$")
+            } else {
+                query.to_string()
+            };
+            Matcher::Regex(Regex::build(&pattern, options.ignore_case)?)
+        } else if options.ignore_case {
+            Matcher::CaseInsensitive(fold::fold(query))
+        } else {
+            Matcher::Literal(query.to_string())
+        };
+        Ok(Searcher { matcher, options })
+    }
+
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    // Whether a match passes the -w and -x options.
+    fn keeps(&self, line: &str, start: usize, end: usize) -> bool {
+        if self.options.line_regexp && (start != 0 || end != line.len()) {
+            return false;
+        }
+        // Like grep, a word is a match that isn't touching a letter, a digit or an underscore on either side.
+        if self.options.word_regexp {
+            let before = line[..start].chars().next_back().is_some_and(is_word_char);
+            let after = line[end..].chars().next().is_some_and(is_word_char);
+            return !before && !after;
+        }
+        true
+    }
+
+    /// Returns the byte range of every match in the line that passes the -w and -x options.
+    pub fn find_iter(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut spans = self.matcher.find_iter(line);
+        spans.retain(|&(start, end)| self.keeps(line, start, end));
+        spans
+    }
+
+    /// With --fuzzy, the distance of the closest match in the line. None for the other matchers, or if nothing matches.
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        self.matcher
+            .fuzzy_matches(line)
+            .into_iter()
+            .filter(|m| self.keeps(line, m.start, m.end))
+            .map(|m| m.distance)
+            .min()
+    }
+
+    /// Whether the matching lines are ranked by distance instead of being in order, only with --fuzzy (and not -v).
+    pub fn ranks(&self) -> bool {
+        matches!(self.matcher, Matcher::Fuzzy(_)) && !self.options.invert_match
+    }
+
+    /// Returns the spans to show if the line is selected, or None if it isn't.
+    /// With -v a selected line doesn't contain any match, so its spans are empty.
+    pub fn matches(&self, line: &str) -> Option<Vec<(usize, usize)>> {
+        let spans = self.find_iter(line);
+        match (spans.is_empty(), self.options.invert_match) {
+            (false, false) => Some(spans),
+            (true, true) => Some(Vec::new()),
+            _ => None,
+        }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.matches(line).is_some()
+    }
+
+    pub fn search<'a>(&self, contents: &'a str) -> Vec<&'a str> {
+        contents
+            .lines()
+            .filter(|line| self.is_match(line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search<'a>(query: &str, options: SearchOptions, contents: &'a str) -> Vec<&'a str> {
+        Searcher::build(query, options).unwrap().search(contents)
+    }
+
+    #[test]
+    fn case_insensitive_spans() {
+        let matcher = Matcher::CaseInsensitive(String::from("rust"));
+        assert_eq!(matcher.find_iter("Trust RUST"), vec![(1, 5), (6, 10)]);
+        // 'İ' folds to two characters, the span still covers its original bytes.
+        let matcher = Matcher::CaseInsensitive(fold::fold("İx"));
+        assert_eq!(matcher.find_iter("aİx"), vec![(1, 4)]);
+    }
+
+    #[test]
+    fn invert_word_and_line() {
+        let contents = "Rust:\nsafe, fast, productive.\nTrust me.\nrust";
+        let invert = SearchOptions {
+            invert_match: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(
+            search("rust", invert, contents),
+            vec!["Rust:", "safe, fast, productive."]
+        );
+
+        let word = SearchOptions {
+            word_regexp: true,
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search("rust", word, contents), vec!["Rust:", "rust"]);
+
+        let line = SearchOptions {
+            line_regexp: true,
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search("rust", line, contents), vec!["rust"]);
+    }
+
+    #[test]
+    fn many_queries() {
+        let contents = "Rust:\nsafe, fast, productive.\nPick three.";
+        let searcher = Searcher::build_many(
+            &["fast", "PICK"],
+            SearchOptions {
+                ignore_case: true,
+                ..SearchOptions::default()
+            },
+        );
+        assert_eq!(
+            searcher.unwrap().search(contents),
+            vec!["safe, fast, productive.", "Pick three."]
+        );
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let searcher = Searcher::build_many(&["^R", r"\.$"], regex).unwrap();
+        assert_eq!(searcher.search(contents).len(), 3);
+        let nothing: [&str; 0] = [];
+        assert!(Searcher::build_many(&nothing, regex)
+            .unwrap()
+            .search(contents)
+            .is_empty());
+    }
+
+    #[test]
+    fn line_regexp_with_regex_alternation() {
+        let options = SearchOptions {
+            regex: true,
+            line_regexp: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search("a|ab", options, "ab\nabc\na"), vec!["ab", "a"]);
+    }
+}
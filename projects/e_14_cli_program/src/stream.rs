@@ -0,0 +1,326 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    io::{self, BufRead, Read},
+    str,
+};
+
+use crate::{
+    context::Line,
+    decode::{self, Bom, Utf16Reader},
+    Match, Searcher,
+};
+
+/// The options that change how an input is read, Config::read_options builds them from the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReadOptions {
+    pub before: usize, // Lines of context before each match (-B).
+    pub after: usize,  // Lines of context after each match (-A).
+    pub text: bool,    // Never treat the input as binary (-a).
+    pub lossy: bool,   // Decode UTF-16 and Latin-1 instead of treating them as binary (--lossy).
+}
+
+// A whole line has to be in memory to be searched, so an input without newlines (a minified bundle,
+// a binary file read with --lossy) would be loaded whole. A longer line stops the search of the input with an error.
+const MAX_LINE: usize = 64 * 1024 * 1024;
+
+/// What search_reader found in an input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize, // The number of lines that matched.
+    // The input has a NUL byte or isn't valid UTF-8, like grep we don't print the lines of a binary file.
+    pub binary: bool,
+}
+
+// A match kept until the end of the input, to be ranked by its distance.
+struct Ranked {
+    distance: usize,
+    line_number: usize,
+    offset: usize,
+    line: String,
+    spans: Vec<(usize, usize)>,
+}
+
+/// Searches the input one line at a time and calls `f` with every match and context line, in order.
+///
+/// Only the current line (and the `before` lines kept for context) is in memory,
+/// so the input can be much bigger than the available memory, a pipe or stdin.
+/// A line longer than 64 MiB is an `InvalidData` error, the lines before it were already given to `f`.
+///
+/// Once the input turns out to be binary `f` isn't called anymore, but the matching lines are still counted.
+/// With `options.lossy` the input is decoded first, see the decode module.
+///
+/// When the searcher ranks its matches (--fuzzy), the matching lines are kept until the end of the input
+/// and then given to `f` from the closest to the furthest, without context lines.
+pub fn search_reader<R: BufRead>(
+    searcher: &Searcher,
+    mut reader: R,
+    options: ReadOptions,
+    f: impl FnMut(Line) -> io::Result<()>,
+) -> io::Result<Summary> {
+    if options.lossy {
+        match Bom::detect(reader.fill_buf()?) {
+            Some(bom @ (Bom::Utf16Le | Bom::Utf16Be)) => {
+                reader.consume(bom.size());
+                let reader = Utf16Reader::new(reader, bom == Bom::Utf16Be);
+                return search_lines(searcher, reader, options, f);
+            }
+            Some(bom) => reader.consume(bom.size()),
+            None => {}
+        }
+    }
+    search_lines(searcher, reader, options, f)
+}
+
+fn search_lines<R: BufRead>(
+    searcher: &Searcher,
+    mut reader: R,
+    options: ReadOptions,
+    mut f: impl FnMut(Line) -> io::Result<()>,
+) -> io::Result<Summary> {
+    let rank = searcher.ranks();
+    let options = if rank {
+        ReadOptions {
+            before: 0,
+            after: 0,
+            ..options
+        }
+    } else {
+        options
+    };
+    let mut ranked = Vec::new();
+    // Like grep, a NUL byte near the start is enough to call the whole input binary, before printing anything.
+    let mut binary = !options.text && reader.fill_buf()?.contains(&0);
+    // The same buffer is reused for every line, so reading doesn't allocate after the first long line.
+    let mut buffer = Vec::new();
+    // The last lines that didn't match, waiting in case the next line is a match and needs them as context.
+    let mut kept: VecDeque<(usize, usize, String)> = VecDeque::new();
+    // Not with_capacity(options.before): the buffer only grows as lines arrive, so a huge -B costs nothing up front.
+    let mut after_left = 0;
+    let mut line_number = 0;
+    let mut offset = 0;
+    let mut count = 0;
+
+    loop {
+        buffer.clear();
+        // One more byte than the limit, so a line of exactly MAX_LINE bytes and its '\n' still fit.
+        let read = (&mut reader)
+            .take(MAX_LINE as u64 + 1)
+            .read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if read > MAX_LINE && buffer.last() != Some(&b'\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "line {} is longer than {} MiB",
+                    line_number + 1,
+                    MAX_LINE / 1024 / 1024
+                ),
+            ));
+        }
+        line_number += 1;
+        let bytes = trim_line_ending(&buffer);
+        let line = match str::from_utf8(bytes) {
+            Ok(line) => Cow::Borrowed(line),
+            Err(_) if options.lossy => Cow::Owned(decode::latin1(bytes)),
+            Err(_) => {
+                binary |= !options.text;
+                String::from_utf8_lossy(bytes)
+            }
+        };
+        binary |= !options.text && bytes.contains(&0);
+        let line = line.as_ref();
+
+        if let Some(spans) = searcher.matches(line) {
+            count += 1;
+            if rank && !binary {
+                ranked.push(Ranked {
+                    distance: searcher.distance(line).unwrap_or(0),
+                    line_number,
+                    offset,
+                    line: line.to_string(),
+                    spans,
+                });
+            } else if !binary {
+                for (line_number, offset, line) in kept.drain(..) {
+                    f(Line::Context {
+                        line_number,
+                        offset,
+                        line: &line,
+                    })?;
+                }
+                f(Line::Match(Match {
+                    line_number,
+                    offset,
+                    line,
+                    spans,
+                }))?;
+            }
+            after_left = options.after;
+        } else if binary {
+            // Nothing is printed from now on, so there is no context to keep.
+            kept.clear();
+        } else if after_left > 0 {
+            after_left -= 1;
+            f(Line::Context {
+                line_number,
+                offset,
+                line,
+            })?;
+        } else if options.before > 0 {
+            // When the queue is full we reuse the String of the oldest line instead of allocating a new one.
+            let mut kept_line = if kept.len() == options.before {
+                kept.pop_front().unwrap().2
+            } else {
+                String::new()
+            };
+            kept_line.clear();
+            kept_line.push_str(line);
+            kept.push_back((line_number, offset, kept_line));
+        }
+        // Offsets count the bytes of the text that was searched, so they only differ from the file when it was decoded.
+        offset += read - bytes.len() + line.len();
+    }
+
+    // sort_by_key is stable, so lines at the same distance stay in the order of the input.
+    ranked.sort_by_key(|m| m.distance);
+    for m in ranked {
+        f(Line::Match(Match {
+            line_number: m.line_number,
+            offset: m.offset,
+            line: &m.line,
+            spans: m.spans,
+        }))?;
+    }
+    Ok(Summary { count, binary })
+}
+
+// Removes "\n" or "\r\n" from the end of the line, the same way str::lines does.
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    match line.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_matches, SearchOptions};
+
+    #[test]
+    fn same_matches_as_search_matches() {
+        let contents =
+            "I'm nobody! Who are you?\r\nAre you nobody, too?\nThen there's a pair of us\n";
+        let searcher = Searcher::build("nobody", SearchOptions::default()).unwrap();
+        let mut streamed = Vec::new();
+        let options = ReadOptions::default();
+        let summary = search_reader(&searcher, contents.as_bytes(), options, |line| {
+            if let Line::Match(m) = line {
+                streamed.push((m.line_number, m.offset, m.line.to_string(), m.spans));
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        let expected: Vec<_> = search_matches(&searcher, contents)
+            .into_iter()
+            .map(|m| (m.line_number, m.offset, m.line.to_string(), m.spans))
+            .collect();
+        assert_eq!(summary.count, 2);
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn context_lines() {
+        let contents = "a\nb\nmatch\nc\nd\ne\nmatch\nf";
+        let searcher = Searcher::build("match", SearchOptions::default()).unwrap();
+        let mut lines = Vec::new();
+        let options = ReadOptions {
+            before: 1,
+            after: 1,
+            ..ReadOptions::default()
+        };
+        search_reader(&searcher, contents.as_bytes(), options, |line| {
+            lines.push(match line {
+                Line::Match(m) => (m.line_number, true),
+                Line::Context { line_number, .. } => (line_number, false),
+            });
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                (2, false),
+                (3, true),
+                (4, false),
+                (6, false),
+                (7, true),
+                (8, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_and_lossy() {
+        let searcher = Searcher::build("café", SearchOptions::default()).unwrap();
+        let search = |contents: &[u8], options: ReadOptions| {
+            let mut lines = Vec::new();
+            let summary = search_reader(&searcher, contents, options, |line| {
+                if let Line::Match(m) = line {
+                    lines.push(m.line.to_string());
+                }
+                Ok(())
+            })
+            .unwrap();
+            (summary.count, summary.binary, lines)
+        };
+
+        // The first line is printed before the invalid UTF-8 shows up, the last one isn't.
+        let latin1 = b"caf\xC3\xA9\ncaf\xE9\ncaf\xC3\xA9";
+        assert_eq!(
+            search(latin1, ReadOptions::default()),
+            (2, true, vec![String::from("café")])
+        );
+        let lossy = ReadOptions {
+            lossy: true,
+            ..ReadOptions::default()
+        };
+        assert_eq!(search(latin1, lossy).0, 3);
+        assert!(!search(latin1, lossy).1);
+
+        let nul = b"caf\xC3\xA9\0\n";
+        assert_eq!(search(nul, ReadOptions::default()), (1, true, vec![]));
+        let text = ReadOptions {
+            text: true,
+            ..ReadOptions::default()
+        };
+        assert_eq!(search(nul, text), (1, false, vec![String::from("café\0")]));
+
+        // "café" in UTF-16 with a little-endian BOM.
+        let utf16 = b"\xFF\xFEc\0a\0f\0\xE9\0\n\0";
+        assert_eq!(search(utf16, ReadOptions::default()).0, 0);
+        assert_eq!(search(utf16, lossy), (1, false, vec![String::from("café")]));
+    }
+
+    #[test]
+    fn line_too_long() {
+        let searcher = Searcher::build("x", SearchOptions::default()).unwrap();
+        let mut found = 0;
+        let reader = io::BufReader::new(
+            io::Cursor::new("x\n").chain(io::repeat(b'x').take(MAX_LINE as u64 + 1)),
+        );
+        let error = search_reader(&searcher, reader, ReadOptions::default(), |_| {
+            found += 1;
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 2 is longer than 64 MiB");
+        // The line before the long one was already searched.
+        assert_eq!(found, 1);
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::ignore::Filter;
+
+// The error keeps the path that failed, so a warning can say which file or directory was the problem.
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl Error for WalkError {}
+
+/// An iterator over every file under the given paths.
+///
+/// Files are yielded as they are, directories are walked recursively in sorted order.
+/// Symbolic links found inside a directory are skipped (like `grep -r`) so a link can't make the walk loop forever.
+pub struct Walk {
+    stack: Vec<PathBuf>,
+    roots: std::vec::IntoIter<PathBuf>,
+    root: PathBuf, // The path the entries in the stack were found in.
+    filter: Filter,
+}
+
+impl Walk {
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> Walk {
+        Walk::with_filter(paths, Filter::default())
+    }
+
+    /// Like new, but the entries of the directories that the filter excludes are skipped.
+    pub fn with_filter<P: AsRef<Path>>(paths: &[P], filter: Filter) -> Walk {
+        let roots: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        Walk {
+            stack: Vec::new(),
+            roots: roots.into_iter(),
+            root: PathBuf::new(),
+            filter,
+        }
+    }
+
+    fn read_dir(&mut self, dir: &Path) -> Result<(), WalkError> {
+        let error = |error| WalkError {
+            path: dir.to_path_buf(),
+            error,
+        };
+        // The ignore files of the directory apply to its own entries too, so they are read first.
+        self.filter.enter_dir(dir).map_err(error)?;
+        let mut children = Vec::new();
+        for entry in fs::read_dir(dir).map_err(error)? {
+            let entry = entry.map_err(error)?;
+            let file_type = entry.file_type().map_err(error)?;
+            let path = entry.path();
+            if !file_type.is_symlink()
+                && !self
+                    .filter
+                    .is_excluded(&self.root, &path, file_type.is_dir())
+            {
+                children.push(path);
+            }
+        }
+        // The stack pops from the end, so we sort in reverse to visit the entries in alphabetical order.
+        children.sort_by(|a, b| b.cmp(a));
+        self.stack.extend(children);
+        Ok(())
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<PathBuf, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let path = match self.stack.pop() {
+                Some(path) => path,
+                None => {
+                    let root = self.roots.next()?;
+                    self.root = root.clone();
+                    root
+                }
+            };
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(error) => return Some(Err(WalkError { path, error })),
+            };
+            if metadata.is_dir() && path == self.root {
+                if let Err(error) = self.filter.enter_root(&path) {
+                    return Some(Err(WalkError { path, error }));
+                }
+            }
+            if !metadata.is_dir() {
+                return Some(Ok(path));
+            }
+            if let Err(error) = self.read_dir(&path) {
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_directories_and_missing_paths() {
+        let results: Vec<_> = Walk::new(&["poem.txt", "missing.txt", "src"]).collect();
+        assert_eq!(results[0].as_ref().unwrap(), Path::new("poem.txt"));
+        assert_eq!(
+            results[1].as_ref().unwrap_err().path,
+            Path::new("missing.txt")
+        );
+
+        let files: Vec<PathBuf> = results.into_iter().skip(2).map(Result::unwrap).collect();
+        assert!(files.contains(&PathBuf::from("src/walk.rs")));
+        let mut sorted = files.clone();
+        sorted.sort();
+        assert_eq!(files, sorted);
+    }
+
+    #[test]
+    fn filtered_walk() {
+        let dir = std::env::temp_dir().join(format!("minigrep-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::write(dir.join(".gitignore"), "/target\n*.tmp\n").unwrap();
+        for file in [
+            "src/main.rs",
+            "src/notes.tmp",
+            "target/debug/out.rs",
+            "README.md",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let filter = Filter::new(&[], &[], true);
+        let files: Vec<PathBuf> = Walk::with_filter(&[&dir], filter)
+            .map(|path| path.unwrap().strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from("README.md"),
+                PathBuf::from("src/main.rs")
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
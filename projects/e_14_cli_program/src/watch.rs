@@ -0,0 +1,383 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{File, Metadata},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    context::Line, output::Printer, stream::search_reader, walk::Walk, Config, Match, Searcher,
+};
+
+// --watch works like `tail -F file | grep query`: the files are checked again every POLL_INTERVAL,
+// and only the lines added since the last check are searched.
+// - The files that exist when minigrep starts are watched from their end, the files that show up later
+//   (a new file in a watched directory, or a log that was rotated) are read from their start.
+//   A file that was only renamed, like the old log after a rotation, goes on from where it was.
+// - Only the bytes after the last position are read, and a line is only searched once its '\n' arrived.
+// - A file that got smaller was truncated, and a file with a different inode (on Unix) was replaced,
+//   like when a log is rotated. In both cases it is read again from its start.
+// - The new bytes are read READ_CHUNK at a time, so a big new file isn't loaded at once. A line longer than
+//   MAX_LINE is skipped instead of being kept in memory until its '\n' arrives.
+// Like tail, a file that was truncated and then grew past the old position between two checks can't be noticed.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const READ_CHUNK: usize = 64 * 1024;
+const MAX_LINE: usize = 1024 * 1024;
+
+/// The lines added to a file since the last poll.
+#[derive(Debug, PartialEq)]
+struct Appended {
+    bytes: Vec<u8>,      // Only whole lines, every one of them ends with '\n'.
+    lines_before: usize, // The number of lines in the file before these ones.
+    offset: usize,       // The byte offset of the first line in the file.
+}
+
+// Follows one file, remembering how far it was read.
+struct Tail {
+    path: PathBuf,
+    identity: Option<(u64, u64)>,
+    position: u64,    // Everything before this was already read.
+    lines: usize, // The number of lines before `position` (only counted when it starts at 0, or with -n).
+    partial: Vec<u8>, // The start of a line whose '\n' didn't arrive yet.
+    skip_line: bool, // Started in the middle of a line, the rest of it is skipped.
+}
+
+// On Unix a file is identified by its device and inode, so we can tell when a path points to a new file.
+#[cfg(unix)]
+fn identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// Elsewhere only truncation can be noticed.
+#[cfg(not(unix))]
+fn identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+impl Tail {
+    // `from_end` starts after what the file already has. The lines before it are only counted with `count_lines`,
+    // because that means reading the whole file once.
+    fn open(path: &Path, from_end: bool, count_lines: bool) -> io::Result<Tail> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut tail = Tail {
+            path: path.to_path_buf(),
+            identity: identity(&metadata),
+            position: 0,
+            lines: 0,
+            partial: Vec::new(),
+            skip_line: false,
+        };
+        if from_end && metadata.len() > 0 {
+            tail.position = metadata.len();
+            let mut file = BufReader::new(file);
+            if count_lines {
+                let mut reader = (&mut file).take(tail.position);
+                loop {
+                    let buffer = reader.fill_buf()?;
+                    if buffer.is_empty() {
+                        break;
+                    }
+                    let length = buffer.len();
+                    tail.lines += buffer.iter().filter(|&&b| b == b'\n').count();
+                    reader.consume(length);
+                }
+            }
+            let mut last = [0];
+            file.seek(SeekFrom::Start(tail.position - 1))?;
+            file.read_exact(&mut last)?;
+            tail.skip_line = last[0] != b'\n';
+        }
+        Ok(tail)
+    }
+
+    // Reads the next lines added since the last poll, None when there isn't a new whole line.
+    // It stops after the first chunk with a '\n', so it is called again until it returns None.
+    fn poll(&mut self) -> io::Result<Option<Appended>> {
+        // The metadata comes from the open file and not from the path, so it can't be from another file.
+        let mut file = File::open(&self.path)?;
+        let metadata = file.metadata()?;
+        let identity = identity(&metadata);
+        if identity != self.identity || metadata.len() < self.position {
+            self.identity = identity;
+            self.position = 0;
+            self.lines = 0;
+            self.partial.clear();
+            self.skip_line = false;
+        }
+        if metadata.len() == self.position {
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(self.position))?;
+        let mut bytes = mem::take(&mut self.partial);
+        let mut offset = self.position as usize - bytes.len();
+        let mut chunk = vec![0; READ_CHUNK];
+        loop {
+            let length = file.read(&mut chunk)?;
+            if length == 0 {
+                break;
+            }
+            self.position += length as u64;
+            let mut read = &chunk[..length];
+            // While a line is skipped nothing is kept, so bytes is empty.
+            if self.skip_line {
+                let Some(end) = read.iter().position(|&b| b == b'\n') else {
+                    continue;
+                };
+                read = &read[end + 1..];
+                offset = self.position as usize - read.len();
+                self.lines += 1;
+                self.skip_line = false;
+            }
+            bytes.extend_from_slice(read);
+            if read.contains(&b'\n') {
+                break;
+            }
+            if bytes.len() > MAX_LINE {
+                bytes.clear();
+                self.skip_line = true;
+            }
+        }
+        // The last line isn't searched until it is complete.
+        let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        self.partial = bytes.split_off(complete);
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        let lines_before = self.lines;
+        self.lines += bytes.iter().filter(|&&b| b == b'\n').count();
+        Ok(Some(Appended {
+            bytes,
+            lines_before,
+            offset,
+        }))
+    }
+}
+
+// The lines of the appended bytes are numbered from 1, they move to their place in the whole file.
+fn shift<'a>(line: Line<'a>, appended: &Appended) -> Line<'a> {
+    match line {
+        Line::Match(m) => Line::Match(Match {
+            line_number: m.line_number + appended.lines_before,
+            offset: m.offset + appended.offset,
+            ..m
+        }),
+        Line::Context {
+            line_number,
+            offset,
+            line,
+        } => Line::Context {
+            line_number: line_number + appended.lines_before,
+            offset: offset + appended.offset,
+            line,
+        },
+    }
+}
+
+fn print_appended<W: Write>(
+    config: &Config,
+    searcher: &Searcher,
+    printer: &mut Printer<W>,
+    name: &str,
+    appended: &Appended,
+) -> io::Result<()> {
+    // Most of what a log gets doesn't match, and with --json every start and finish is an event,
+    // so the lines are searched once without printing and only printed when there is a match.
+    let options = config.read_options();
+    let summary = search_reader(searcher, appended.bytes.as_slice(), options, |_| Ok(()))?;
+    if summary.count == 0 {
+        return Ok(());
+    }
+    printer.start(name)?;
+    let summary = search_reader(searcher, appended.bytes.as_slice(), options, |line| {
+        printer.line(&shift(line, appended))
+    })?;
+    printer.finish(summary)
+}
+
+/// The --watch mode of run: prints the new matching lines of the paths until minigrep is stopped,
+/// or until the output can't be written anymore.
+pub(crate) fn watch<W: Write>(
+    config: &Config,
+    searcher: &Searcher,
+    printer: &mut Printer<W>,
+    paths: &[String],
+) -> io::Result<()> {
+    let filter = config.filter();
+    let mut tails: BTreeMap<PathBuf, Tail> = BTreeMap::new();
+    // A missing file would be reported every poll, so every problem is only reported once, until it is gone.
+    let mut warned = BTreeSet::new();
+    let mut first = true;
+    loop {
+        let mut seen = BTreeSet::new();
+        let mut problems = BTreeSet::new();
+        // Where every file was, by identity. A rotated log is often renamed next to the new one,
+        // it goes on from there instead of being read again from its start as a new file.
+        let known: HashMap<(u64, u64), (u64, usize)> = tails
+            .values()
+            .filter_map(|tail| Some((tail.identity?, (tail.position, tail.lines))))
+            .collect();
+        for path in paths {
+            if path == "-" {
+                problems.insert(String::from("standard input can't be watched"));
+                continue;
+            }
+            for file in Walk::with_filter(&[path], filter.clone()) {
+                let file = match file {
+                    Ok(file) => file,
+                    Err(e) => {
+                        problems.insert(e.to_string());
+                        continue;
+                    }
+                };
+                let name = file.display().to_string();
+                if !tails.contains_key(&file) {
+                    match Tail::open(&file, first, config.line_number) {
+                        Ok(mut tail) => {
+                            if let Some(&(position, lines)) =
+                                tail.identity.and_then(|identity| known.get(&identity))
+                            {
+                                tail.position = position;
+                                tail.lines = lines;
+                            }
+                            tails.insert(file.clone(), tail)
+                        }
+                        Err(e) => {
+                            problems.insert(format!("{name}: {e}"));
+                            continue;
+                        }
+                    };
+                }
+                let tail = tails.get_mut(&file).unwrap();
+                let polled = loop {
+                    match tail.poll() {
+                        Ok(Some(appended)) => {
+                            print_appended(config, searcher, printer, &name, &appended)?
+                        }
+                        Ok(None) => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                };
+                match polled {
+                    Ok(()) => {}
+                    // Removed between the walk and the poll, it is read from its start if it comes back.
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => {
+                        problems.insert(format!("{name}: {e}"));
+                        continue;
+                    }
+                }
+                seen.insert(file);
+            }
+        }
+        for problem in &problems {
+            if warned.insert(problem.clone()) {
+                eprintln!("minigrep: {problem}");
+            }
+        }
+        warned.retain(|problem| problems.contains(problem));
+        tails.retain(|path, _| seen.contains(path));
+        first = false;
+        printer.flush()?;
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env,
+        fs::{self, OpenOptions},
+        process,
+    };
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn polled(tail: &mut Tail) -> Option<(String, usize, usize)> {
+        let appended = tail.poll().unwrap()?;
+        let text = String::from_utf8(appended.bytes).unwrap();
+        Some((text, appended.lines_before, appended.offset))
+    }
+
+    #[test]
+    fn appends_truncation_and_rotation() {
+        let dir = env::temp_dir().join(format!("minigrep-watch-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        fs::write(&log, "old error\nhalf a li").unwrap();
+
+        // Started from the end: the line that was being written is skipped, only the next ones are read.
+        let mut tail = Tail::open(&log, true, true).unwrap();
+        assert_eq!(polled(&mut tail), None);
+        append(&log, "ne\nnew error\npart");
+        assert_eq!(
+            polled(&mut tail),
+            Some((String::from("new error\n"), 2, 22))
+        );
+        append(&log, "ial error\n");
+        assert_eq!(
+            polled(&mut tail),
+            Some((String::from("partial error\n"), 3, 32))
+        );
+
+        // Truncated: read again from the start.
+        fs::write(&log, "short\n").unwrap();
+        assert_eq!(polled(&mut tail), Some((String::from("short\n"), 0, 0)));
+
+        // Rotated: the old file is renamed and a new one is created at the same path.
+        fs::rename(&log, dir.join("app.log.1")).unwrap();
+        fs::write(&log, "first line of the new log\n").unwrap();
+        if cfg!(unix) {
+            assert_eq!(
+                polled(&mut tail),
+                Some((String::from("first line of the new log\n"), 0, 0))
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_in_chunks_and_skips_huge_lines() {
+        let dir = env::temp_dir().join(format!("minigrep-watch-chunks-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        fs::write(&log, "").unwrap();
+        let mut tail = Tail::open(&log, true, true).unwrap();
+
+        // A new file bigger than a chunk comes in several polls, with the lines in order.
+        let lines: String = (0..READ_CHUNK / 4).map(|i| format!("{i:03}\n")).collect();
+        append(&log, &lines);
+        let mut read = String::new();
+        while let Some((text, lines_before, offset)) = polled(&mut tail) {
+            assert_eq!(offset, read.len());
+            assert_eq!(lines_before, read.lines().count());
+            read += &text;
+        }
+        assert_eq!(read, lines);
+
+        // A line longer than MAX_LINE is never kept whole, the next one is still found at its place.
+        append(&log, &"x".repeat(MAX_LINE + READ_CHUNK));
+        assert_eq!(polled(&mut tail), None);
+        assert!(tail.partial.len() <= MAX_LINE);
+        append(&log, "xxx\nafter\n");
+        let offset = lines.len() + MAX_LINE + READ_CHUNK + 4;
+        let count = READ_CHUNK / 4 + 1;
+        assert_eq!(
+            polled(&mut tail),
+            Some((String::from("after\n"), count, offset))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    io::{self, BufReader},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    request::{Request, RequestError},
+    response::Response,
+    router::Router,
+    shutdown::Shutdown,
+};
+
+// Opening a TCP connection costs a round trip before the first byte of the request, so HTTP/1.1 keeps
+// the connection open after a response and reads the next request from it ("keep-alive").
+// A client can even send several requests without waiting for the answers ("pipelining"): they arrive
+// in one read and wait in the BufReader, and they are answered one after the other, in the order they came.
+//
+// A connection ends when:
+// - the client asks for it with "Connection: close" (or is an HTTP/1.0 client that didn't ask for keep-alive),
+// - it served max_requests requests, the last response then says "Connection: close",
+// - nothing arrived for idle_timeout, or the client closed it,
+// - the request was malformed, because we can't know where the next one starts,
+// - the server is shutting down: the request in progress (or already sent) is answered, and an idle connection is closed.
+//
+// Every open connection keeps a worker of the pool busy, even while it waits, so the idle timeout
+// has to be short, or a few idle browsers are enough to keep the other clients waiting.
+
+/// How long a connection can stay open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeepAlive {
+    pub idle_timeout: Duration, // The longest wait for the next request (and for each read inside one).
+    pub max_requests: usize,    // The connection is closed after this many requests, 0 counts as 1.
+}
+
+impl Default for KeepAlive {
+    fn default() -> KeepAlive {
+        KeepAlive {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+        }
+    }
+}
+
+// HTTP/1.1 keeps the connection by default and HTTP/1.0 closes it, unless the Connection header says otherwise.
+fn keeps_alive(request: &Request) -> bool {
+    if request.headers.has_token("Connection", "close") {
+        return false;
+    }
+    request.version == "HTTP/1.1" || request.headers.has_token("Connection", "keep-alive")
+}
+
+// How often a connection that waits for its next request checks whether the server is shutting down.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Waits until the next request starts arriving, false when the connection should be closed instead.
+// A blocking read can't be interrupted, so the wait is made of short reads that only peek at the stream.
+fn wait_for_request(
+    stream: &TcpStream,
+    idle_timeout: Duration,
+    shutdown: &Shutdown,
+) -> io::Result<bool> {
+    let start = Instant::now();
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let arrived = loop {
+        // The stream is looked at before the shutdown: a connection that waited in the queue of the pool
+        // may already have its request, and the client would get no answer if it was closed.
+        match stream.peek(&mut [0]) {
+            Ok(0) => break false, // The client closed the connection.
+            Ok(_) => break true,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if shutdown.is_requested() || start.elapsed() >= idle_timeout {
+                    break false;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    // Once it started, the request gets the whole idle timeout for each read.
+    stream.set_read_timeout(Some(idle_timeout))?;
+    Ok(arrived)
+}
+
+/// Answers the requests of the connection until it ends, see the comment at the top of this file.
+pub fn handle_connection(
+    stream: TcpStream,
+    router: &Router,
+    keep_alive: KeepAlive,
+    shutdown: &Shutdown,
+) {
+    // Without a timeout a client that never sends anything would keep a worker forever.
+    if let Err(e) = stream.set_read_timeout(Some(keep_alive.idle_timeout)) {
+        eprintln!("Couldn't set the timeout of the connection: {e}");
+        return;
+    }
+    // A &TcpStream can be read and written, so one BufReader keeps the pipelined requests between two reads
+    // while the responses are written to the same stream.
+    let mut buf_reader = BufReader::new(&stream);
+    let mut writer = &stream;
+
+    // A connection that can't serve any request would be closed without an answer.
+    let max_requests = keep_alive.max_requests.max(1);
+    for served in 1..=max_requests {
+        // Pipelined requests are already in the buffer, there is nothing to wait for.
+        if buf_reader.buffer().is_empty() {
+            match wait_for_request(&stream, keep_alive.idle_timeout, shutdown) {
+                Ok(true) => {}
+                Ok(false) | Err(_) => return,
+            }
+        }
+        let (response, with_body, keep) = match Request::read(&mut buf_reader) {
+            Ok(mut request) => {
+                request.remote_addr = stream.peer_addr().ok();
+                let with_body = request.method != "HEAD";
+                let keep = keeps_alive(&request) && served < max_requests;
+                let is_http_1_0 = request.version == "HTTP/1.0";
+                let response = router.handle(request);
+                // A handler can close the connection too, and the server may have started shutting down meanwhile.
+                let keep = keep
+                    && !response.headers().has_token("Connection", "close")
+                    && !shutdown.is_requested();
+                let response = match (keep, is_http_1_0) {
+                    (false, _) if response.headers().has_token("Connection", "close") => response,
+                    (false, _) => response.header("Connection", "close"),
+                    // An HTTP/1.0 client only keeps the connection when the response says so.
+                    (true, true) => response.header("Connection", "keep-alive"),
+                    (true, false) => response,
+                };
+                (response, with_body, keep)
+            }
+            // The client closed the connection, it stopped sending (the idle timeout), or the connection broke.
+            // There is nobody to answer.
+            Err(RequestError::Closed | RequestError::Io(_)) => return,
+            // The client gets the reason as the body, instead of the worker thread panicking on an unwrap.
+            Err(RequestError::Invalid { status, message }) => {
+                let response = Response::new(status)
+                    .header("Connection", "close")
+                    .body(message);
+                (response, true, false)
+            }
+        };
+
+        // The client may already be gone, that's not a reason to stop the worker.
+        if let Err(e) = response.write_to(&mut writer, with_body) {
+            eprintln!("Couldn't send the response: {e}");
+            return;
+        }
+        if !keep {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Instant,
+    };
+
+    // Starts a server for one connection, and returns the client side of it.
+    fn connect(keep_alive: KeepAlive) -> (TcpStream, thread::JoinHandle<()>) {
+        connect_with(keep_alive, Shutdown::new(), "")
+    }
+
+    // Like connect, and the request is sent before the server starts answering the connection.
+    fn connect_with(
+        keep_alive: KeepAlive,
+        shutdown: Shutdown,
+        request: &str,
+    ) -> (TcpStream, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let server = thread::spawn(move || {
+            let mut router = Router::new();
+            router.get("/:name", |request| {
+                Response::new(200).body(request.param("name").unwrap().to_string())
+            });
+            router.get("/bye", |_| {
+                Response::new(200).header("Connection", "close").body("bye")
+            });
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &router, keep_alive, &shutdown);
+        });
+        (client, server)
+    }
+
+    fn get(path: &str, headers: &str) -> String {
+        format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n")
+    }
+
+    #[test]
+    fn pipelined_requests_are_answered_in_order() {
+        let (mut client, server) = connect(KeepAlive::default());
+        let requests = get("/one", "") + &get("/two", "") + &get("/three", "Connection: close\r\n");
+        client.write_all(requests.as_bytes()).unwrap();
+        let mut responses = String::new();
+        // The server closes the connection after the third one, so this ends.
+        client.read_to_string(&mut responses).unwrap();
+        server.join().unwrap();
+
+        let bodies: Vec<&str> = responses
+            .split("HTTP/1.1 200 OK\r\n")
+            .skip(1)
+            .map(|response| response.split("\r\n\r\n").nth(1).unwrap())
+            .collect();
+        assert_eq!(bodies, vec!["one", "two", "three"]);
+        assert_eq!(responses.matches("Connection: close").count(), 1);
+    }
+
+    #[test]
+    fn max_requests_and_idle_timeout() {
+        let keep_alive = KeepAlive {
+            idle_timeout: Duration::from_millis(200),
+            max_requests: 2,
+        };
+        let (mut client, server) = connect(keep_alive);
+        client
+            .write_all((get("/a", "") + &get("/b", "") + &get("/c", "")).as_bytes())
+            .unwrap();
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).unwrap();
+        server.join().unwrap();
+        assert_eq!(responses.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(responses.ends_with("Connection: close\r\nContent-Length: 1\r\n\r\nb"));
+
+        // A client that says nothing is disconnected after the idle timeout.
+        let (mut client, server) = connect(keep_alive);
+        let start = Instant::now();
+        assert_eq!(client.read(&mut [0; 16]).unwrap(), 0);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn http_1_0_closes_unless_asked() {
+        let (mut client, server) = connect(KeepAlive::default());
+        let request =
+            "GET /old HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /new HTTP/1.0\r\n\r\n";
+        client.write_all(request.as_bytes()).unwrap();
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).unwrap();
+        server.join().unwrap();
+        assert!(responses.contains("Connection: keep-alive\r\nContent-Length: 3\r\n\r\nold"));
+        assert!(responses.ends_with("Connection: close\r\nContent-Length: 3\r\n\r\nnew"));
+    }
+
+    #[test]
+    fn a_handler_closing_the_connection_sends_one_header() {
+        let (mut client, server) = connect(KeepAlive::default());
+        client.write_all(get("/bye", "").as_bytes()).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+        assert_eq!(response.matches("Connection: close").count(), 1);
+        assert!(response.ends_with("bye"));
+    }
+
+    #[test]
+    fn zero_max_requests_still_answers_one() {
+        let keep_alive = KeepAlive {
+            max_requests: 0,
+            ..KeepAlive::default()
+        };
+        let (mut client, server) = connect_with(keep_alive, Shutdown::new(), &get("/a", ""));
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+        assert!(response.ends_with("Connection: close\r\nContent-Length: 1\r\n\r\na"));
+    }
+
+    #[test]
+    fn a_request_sent_before_the_shutdown_is_answered() {
+        let shutdown = Shutdown::new();
+        shutdown.request();
+        let (mut client, server) = connect_with(KeepAlive::default(), shutdown, &get("/late", ""));
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\nConnection: close\r\n"));
+        assert!(response.ends_with("late"));
+    }
+}
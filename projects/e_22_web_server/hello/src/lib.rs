@@ -1,8 +1,17 @@
 use std::{
     sync::{mpsc, Arc, Mutex},
     thread,
+    time::{Duration, Instant},
 };
 
+pub mod connection;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod shutdown;
+pub mod static_files;
+
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
@@ -35,7 +44,6 @@ impl ThreadPool {
     /// # Panics
     ///
     /// The `new` function will panic if the size is zero.
-
     pub fn new(size: usize) -> ThreadPool {
         assert!(size > 0);
 
@@ -61,6 +69,38 @@ impl ThreadPool {
         }
     }
 
+    /// Stops the workers once they finished the jobs they already have, waiting at most `deadline` for them.
+    ///
+    /// Returns false when some were still busy at the deadline. They are left running, a thread can't be
+    /// stopped from outside, so they end when the process does.
+    pub fn shutdown(mut self, deadline: Duration) -> bool {
+        drop(self.sender.take()); // The workers take the jobs that are left in the channel, then stop.
+
+        let start = Instant::now();
+        while self.workers.iter().any(|worker| {
+            worker
+                .thread
+                .as_ref()
+                .is_some_and(|thread| !thread.is_finished())
+        }) {
+            if start.elapsed() >= deadline {
+                // Dropping a JoinHandle detaches the thread, so Drop won't wait for it either.
+                for worker in &mut self.workers {
+                    if worker
+                        .thread
+                        .as_ref()
+                        .is_some_and(|thread| !thread.is_finished())
+                    {
+                        worker.thread.take();
+                    }
+                }
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true // Drop joins the threads, which already finished.
+    }
+
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
@@ -1,49 +1,76 @@
-use hello::ThreadPool;
-use std::{
-    fs,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
-    thread,
-    time::Duration,
+use hello::{
+    connection::KeepAlive,
+    response::Response,
+    router::Router,
+    server::Server,
+    shutdown::{admin_handler, random_token, Shutdown, ADMIN_TOKEN_HEADER},
+    static_files::StaticFiles,
 };
+use std::{env, fs, net::TcpListener, thread, time::Duration};
 
 fn main() {
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-    let pool = ThreadPool::new(4);
+    // The server runs until Ctrl+C, SIGTERM or POST /admin/shutdown, then it finishes the requests in progress.
+    let shutdown = Shutdown::new();
+    if !shutdown.on_signals() {
+        eprintln!("Signals aren't supported here, use POST /admin/shutdown to stop the server.");
+    }
 
-    for stream in listener.incoming().take(2) {
-        // We’re only going to process two requests in this example, so we’re using the take method on the iterator to create an iterator that will only produce two items.
-        let stream = stream.unwrap();
+    // The directory of the files under /static/, the first argument: cargo run -- public
+    let root = env::args().nth(1).unwrap_or_else(|| String::from("public"));
+    // The token of POST /admin/shutdown, from HELLO_ADMIN_TOKEN or a new random one. See admin_handler.
+    let token = env::var("HELLO_ADMIN_TOKEN").unwrap_or_else(|_| random_token());
+    eprintln!(
+        "To stop the server: curl -X POST -H '{ADMIN_TOKEN_HEADER}: {token}' http://127.0.0.1:7878/admin/shutdown"
+    );
+    let router = routes(StaticFiles::new(root), &shutdown, token);
+    let server = Server {
+        workers: 4,
+        // A browser reuses its connection for the next requests, instead of paying for a new TCP handshake every time.
+        keep_alive: KeepAlive {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+        },
+        shutdown_deadline: Duration::from_secs(10),
+    };
 
-        pool.execute(|| {
-            handle_connection(stream);
-        });
+    if let Err(e) = server.serve(listener, router, &shutdown) {
+        eprintln!("Server error: {e}");
     }
-
-    println!("Shutting down.");
+    // When main returns the pool was already dropped, so every worker was shut down.
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
-
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
+// Adding an endpoint is adding a route here, the handler gets the request and builds the response.
+fn routes(files: StaticFiles, shutdown: &Shutdown, admin_token: String) -> Router {
+    let mut router = Router::new();
+    router
+        .get("/", |_| page(200, "hello.html"))
+        .get("/sleep", |_| {
             thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "hello.html")
-        }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
-    };
-
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
-
-    let response = format!(
-        "{status_line}\r\n\
-         Content-Length: {length}\r\n\r\n\
-         {contents}"
-    );
+            page(200, "hello.html")
+        })
+        .get("/hello/:name", |request| {
+            let name = request.param("name").unwrap_or("stranger");
+            Response::new(200)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!("Hello, {name}!"))
+        })
+        .get("/static/*path", move |request| files.serve(request))
+        // Only from this machine, and with the token in the X-Admin-Token header: a web page open in a local
+        // browser could reach 127.0.0.1 too, but it can't send that header here.
+        .post(
+            "/admin/shutdown",
+            admin_handler(shutdown.clone(), admin_token),
+        )
+        .fallback(|_| page(404, "404.html"));
+    router
+}
 
-    stream.write_all(response.as_bytes()).unwrap();
+fn page(status: u16, filename: &str) -> Response {
+    match fs::read(filename) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(e) => Response::new(500).body(format!("{filename}: {e}")),
+    }
 }
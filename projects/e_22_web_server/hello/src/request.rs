@@ -0,0 +1,427 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read},
+    net::SocketAddr,
+};
+
+// An HTTP/1.1 request looks like this (RFC 9112), every line ends with CRLF:
+//
+//     POST /users?active=1 HTTP/1.1      <- the request line: method, target and version
+//     Host: 127.0.0.1:7878               <- the headers, one "Name: value" per line
+//     Content-Length: 13
+//                                        <- an empty line ends the headers
+//     {"name":"ok"}                      <- the body, exactly Content-Length bytes
+//
+// Everything comes from the client, so nothing is trusted: a line that is too long, a header without a colon
+// or a body shorter than announced is an error with the status code to answer, and never a panic.
+
+const MAX_LINE: usize = 8 * 1024; // The longest request line or header line.
+const MAX_HEADERS: usize = 100;
+const MAX_BODY: usize = 1024 * 1024;
+
+/// The headers of a request. The names are case-insensitive, and a name can appear more than once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    entries: Vec<(String, String)>, // In the order they were received, with the names as they were written.
+}
+
+impl Headers {
+    pub fn add(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// The value of the first header with this name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The values of every header with this name, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether a header with a comma-separated list of tokens, like "Connection: keep-alive, Upgrade", has this one.
+    /// The tokens are case-insensitive too.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|item| item.trim().eq_ignore_ascii_case(token))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,               // Like "GET", methods are case-sensitive.
+    pub target: String,               // As it was sent: "/users?active=1".
+    pub path: String,                 // The target without the query, percent-decoded: "/users".
+    pub query: Vec<(String, String)>, // The decoded pairs of the query string: [("active", "1")].
+    pub version: String,              // "HTTP/1.1" or "HTTP/1.0".
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    pub params: Vec<(String, String)>, // The parameters of the route's pattern, set by the Router.
+    pub remote_addr: Option<SocketAddr>, // The address of the client, set by the connection.
+}
+
+/// Why a request couldn't be read.
+#[derive(Debug)]
+pub enum RequestError {
+    Closed, // The client closed the connection before sending anything, there is no one to answer.
+    Io(io::Error), // Reading failed, the connection is probably broken.
+    // The client sent something we won't handle, it gets this status code.
+    Invalid { status: u16, message: String },
+}
+
+impl RequestError {
+    fn invalid(status: u16, message: impl Into<String>) -> RequestError {
+        RequestError::Invalid {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn bad(message: impl Into<String>) -> RequestError {
+        RequestError::invalid(400, message)
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::Closed => write!(f, "the connection was closed"),
+            RequestError::Io(e) => write!(f, "{e}"),
+            RequestError::Invalid { status, message } => write!(f, "{status}: {message}"),
+        }
+    }
+}
+
+impl Error for RequestError {}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> RequestError {
+        RequestError::Io(e)
+    }
+}
+
+// The characters allowed in a method or a header name (a "token" in the RFC).
+fn is_token(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+// Reads one line without its CRLF (or a lone LF, which the RFC lets us accept).
+// None when the connection ended before the line started.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    too_long: RequestError,
+) -> Result<Option<String>, RequestError> {
+    let mut line = Vec::new();
+    // One more byte than the limit, so we can tell a line that is exactly MAX_LINE long from a longer one.
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        if line.len() > MAX_LINE {
+            return Err(too_long);
+        }
+        return Err(RequestError::bad(
+            "the request ended in the middle of a line",
+        ));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| RequestError::bad("the request isn't valid UTF-8"))
+}
+
+// Turns %20 into a space. In a query string '+' is a space too.
+fn percent_decode(text: &str, plus_is_space: bool) -> Result<String, RequestError> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'%' => {
+                let hex = rest
+                    .get(..2)
+                    // from_str_radix accepts a sign, "%+1" would be 0x01.
+                    .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| RequestError::bad(format!("invalid escape in '{text}'")))?;
+                bytes.push(hex);
+                rest = &rest[2..];
+            }
+            b'+' if plus_is_space => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| RequestError::bad(format!("'{text}' isn't valid UTF-8")))
+}
+
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, RequestError> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(name, true)?, percent_decode(value, true)?))
+        })
+        .collect()
+}
+
+impl Request {
+    /// Reads the next request from the connection.
+    ///
+    /// The reader is left right after the body, so the next request can be read from it.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Request, RequestError> {
+        // The RFC asks servers to ignore empty lines before the request line, some clients send one after a body.
+        let request_line = loop {
+            match read_line(
+                reader,
+                RequestError::invalid(414, "the request line is too long"),
+            )? {
+                None => return Err(RequestError::Closed),
+                Some(line) if line.is_empty() => continue,
+                Some(line) => break line,
+            }
+        };
+
+        // Exactly one space between the three parts.
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(RequestError::bad(format!(
+                "invalid request line '{request_line}'"
+            )));
+        };
+        if !is_token(method) {
+            return Err(RequestError::bad(format!("invalid method '{method}'")));
+        }
+        match version {
+            "HTTP/1.1" | "HTTP/1.0" => {}
+            _ if version.starts_with("HTTP/") => {
+                return Err(RequestError::invalid(
+                    505,
+                    format!("{version} isn't supported"),
+                ))
+            }
+            _ => return Err(RequestError::bad(format!("invalid version '{version}'"))),
+        }
+
+        // Usually the target is only the path ("origin-form"), but a request to a proxy has the whole URL,
+        // and OPTIONS can be about the whole server: "*".
+        let origin = match target.find("://") {
+            Some(scheme) => {
+                let after = &target[scheme + 3..];
+                after.find('/').map_or("/", |slash| &after[slash..])
+            }
+            None => target,
+        };
+        if !origin.starts_with('/') && origin != "*" {
+            return Err(RequestError::bad(format!("invalid target '{target}'")));
+        }
+        let (path, query) = origin.split_once('?').unwrap_or((origin, ""));
+        // A fragment (#top) is never sent, but if it is it isn't part of the path.
+        let query = query.split('#').next().unwrap_or("");
+        let path = percent_decode(path.split('#').next().unwrap_or(""), false)?;
+        let query = parse_query(query)?;
+
+        let mut headers = Headers::default();
+        loop {
+            let too_long = RequestError::invalid(431, "a header line is too long");
+            let Some(line) = read_line(reader, too_long)? else {
+                return Err(RequestError::bad("the request ended in the headers"));
+            };
+            if line.is_empty() {
+                break;
+            }
+            // A line starting with a space continued the previous header in old HTTP, the RFC says to reject it.
+            if line.starts_with([' ', '\t']) {
+                return Err(RequestError::bad("folded header lines aren't allowed"));
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(RequestError::bad(format!("invalid header line '{line}'")));
+            };
+            // "Host : x" isn't allowed either, there can't be spaces before the colon.
+            if !is_token(name) {
+                return Err(RequestError::bad(format!("invalid header name '{name}'")));
+            }
+            if headers.entries.len() == MAX_HEADERS {
+                return Err(RequestError::invalid(431, "too many headers"));
+            }
+            headers.add(name, value.trim_matches([' ', '\t']));
+        }
+
+        if version == "HTTP/1.1" && headers.get("Host").is_none() {
+            return Err(RequestError::bad("the Host header is missing"));
+        }
+        // We don't decode chunked bodies. Guessing the length would let a client hide a second request in the body.
+        if headers.get("Transfer-Encoding").is_some() {
+            return Err(RequestError::invalid(
+                501,
+                "Transfer-Encoding isn't supported, send a Content-Length",
+            ));
+        }
+
+        // Several Content-Length headers are only fine when they agree.
+        let mut length: Option<usize> = None;
+        for value in headers.get_all("Content-Length") {
+            let parsed = value
+                .parse()
+                .ok()
+                .filter(|_| value.bytes().all(|b| b.is_ascii_digit()))
+                .ok_or_else(|| RequestError::bad(format!("invalid Content-Length '{value}'")))?;
+            if length.is_some_and(|length| length != parsed) {
+                return Err(RequestError::bad("the Content-Length headers don't agree"));
+            }
+            length = Some(parsed);
+        }
+        let length = length.unwrap_or(0);
+        if length > MAX_BODY {
+            return Err(RequestError::invalid(413, "the body is too large"));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => {
+                RequestError::bad("the body is shorter than its Content-Length")
+            }
+            _ => RequestError::Io(e),
+        })?;
+
+        Ok(Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            path,
+            query,
+            version: version.to_string(),
+            headers,
+            body,
+            params: Vec::new(),
+            remote_addr: None,
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// The value of a parameter of the route, like "42" for "id" when "/users/:id" matched "/users/42".
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of the first query parameter with this name.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Request, RequestError> {
+        Request::read(&mut text.as_bytes())
+    }
+
+    fn status(text: &str) -> u16 {
+        match parse(text) {
+            Err(RequestError::Invalid { status, .. }) => status,
+            other => panic!("expected an invalid request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_a_request() {
+        let text = "POST http://localhost/users/J%C3%BCrgen?active=1&name=a+b&flag HTTP/1.1\r\n\
+                    host: localhost\r\n\
+                    Accept: text/html\r\n\
+                    ACCEPT:  application/json \r\n\
+                    Content-Length: 5\r\n\
+                    \r\n\
+                    hello";
+        let mut reader = text.as_bytes();
+        let request = Request::read(&mut reader).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/users/Jürgen");
+        assert_eq!(request.query("name"), Some("a b"));
+        assert_eq!(request.query("flag"), Some(""));
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(
+            request.headers.get_all("accept").collect::<Vec<_>>(),
+            vec!["text/html", "application/json"]
+        );
+        assert_eq!(request.body, b"hello");
+        assert!(reader.is_empty());
+
+        // Bare LF line endings are accepted, and HTTP/1.0 doesn't need a Host.
+        let request = parse("GET / HTTP/1.0\n\n").unwrap();
+        assert_eq!((request.path.as_str(), request.body.len()), ("/", 0));
+        assert!(matches!(parse(""), Err(RequestError::Closed)));
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert_eq!(status("GET /\r\n\r\n"), 400);
+        assert_eq!(status("GET  / HTTP/1.1\r\nHost: x\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/2.0\r\nHost: x\r\n\r\n"), 505);
+        assert_eq!(status("GET index.html HTTP/1.1\r\nHost: x\r\n\r\n"), 400);
+        assert_eq!(status("GET /%zz HTTP/1.1\r\nHost: x\r\n\r\n"), 400);
+        assert_eq!(status("GET /%+1 HTTP/1.1\r\nHost: x\r\n\r\n"), 400);
+        assert_eq!(status("GET /?a=%-1 HTTP/1.1\r\nHost: x\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nHost x\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nHost : x\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nHost: x\r\n folded\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nHost: x\r\n"), 400);
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: -1\r\n\r\n"),
+            400
+        );
+        assert_eq!(
+            status(
+                "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"
+            ),
+            400
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nshort"),
+            400
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            501
+        );
+        let long = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status(&long), 414);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+};
+
+use crate::request::Headers;
+
+/// The reason phrase of the status codes the server uses, like "Not Found" for 404.
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+/// An HTTP response, built step by step:
+///
+/// ```
+/// use hello::response::Response;
+///
+/// let response = Response::new(200)
+///     .header("Content-Type", "text/plain")
+///     .body("Hello!");
+/// assert_eq!(response.status(), 200);
+/// ```
+#[derive(Debug)]
+pub struct Response {
+    status: u16,
+    headers: Headers,
+    body: Body,
+}
+
+// A file isn't read into memory, it is copied to the connection a buffer at a time when the response is written.
+#[derive(Debug)]
+enum Body {
+    Bytes(Vec<u8>),
+    File { file: File, length: u64 },
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::default(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    // Every method takes self and gives it back, so the calls can be chained.
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.add(name, value);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// Sends the first `length` bytes of the file as the body, usually its whole size from its metadata.
+    pub fn file(mut self, file: File, length: u64) -> Response {
+        self.body = Body::File { file, length };
+        self
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The body, None when it is a file that is only read while the response is written.
+    pub fn body_bytes(&self) -> Option<&[u8]> {
+        match &self.body {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::File { .. } => None,
+        }
+    }
+
+    /// Writes the status line, the headers and the body. Content-Length is added from the body.
+    ///
+    /// The answer to a HEAD request is the same without the body, so `with_body` is false for it.
+    pub fn write_to<W: Write>(&self, writer: &mut W, with_body: bool) -> io::Result<()> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason_phrase(self.status)
+        );
+        for (name, value) in self.headers.iter() {
+            head += &format!("{name}: {value}\r\n");
+        }
+        let length = match &self.body {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File { length, .. } => *length,
+        };
+        head += &format!("Content-Length: {length}\r\n\r\n");
+        writer.write_all(head.as_bytes())?;
+        if !with_body {
+            return writer.flush();
+        }
+        match &self.body {
+            Body::Bytes(bytes) => writer.write_all(bytes)?,
+            Body::File { file, length } => {
+                // A &File can be read too, so the response doesn't have to be mutable.
+                let copied = io::copy(&mut file.take(*length), writer)?;
+                // The file got shorter since its size was sent, the client would wait forever for the rest.
+                if copied < *length {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the file got shorter while it was sent",
+                    ));
+                }
+            }
+        }
+        writer.flush()
+    }
+}
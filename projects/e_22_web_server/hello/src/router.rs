@@ -0,0 +1,264 @@
+use crate::{request::Request, response::Response};
+
+// A route is a method and a pattern of path segments:
+// - "/users" only matches /users (a trailing slash doesn't matter).
+// - "/users/:id" matches /users/42, and the handler gets request.param("id") == Some("42").
+// - "/static/*path" matches /static/css/site.css with path = "css/site.css", the wildcard takes the rest of the path.
+// When several patterns match, the most specific one wins: a literal segment beats a parameter, which beats a wildcard.
+// So "/users/new" can be registered next to "/users/:id" in any order.
+
+/// The function that answers the requests of a route.
+/// The router is shared by the worker threads, so handlers must be Send and Sync.
+pub type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Segment {
+    // Higher is more specific.
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Literal(_) => 2,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 0,
+        }
+    }
+}
+
+struct Route {
+    method: String,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+// The parameters of the path if it matches the pattern.
+fn matches(pattern: &[Segment], path: &str) -> Option<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    let mut parts = segments(path);
+    for segment in pattern {
+        match segment {
+            Segment::Wildcard(name) => {
+                let rest: Vec<&str> = parts.by_ref().collect();
+                params.push((name.clone(), rest.join("/")));
+                return Some(params);
+            }
+            Segment::Literal(literal) => {
+                if parts.next()? != literal {
+                    return None;
+                }
+            }
+            Segment::Param(name) => params.push((name.clone(), parts.next()?.to_string())),
+        }
+    }
+    // Every part of the path has to be used by the pattern.
+    match parts.next() {
+        Some(_) => None,
+        None => Some(params),
+    }
+}
+
+/// Sends every request to the handler of the route that matches its method and path.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Option<Handler>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Adds a route, see the comment at the top of this file for the patterns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the pattern, because it takes the rest of the path.
+    pub fn route<F>(&mut self, method: &str, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let pattern: Vec<Segment> = segments(pattern)
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+        let wildcard = pattern
+            .iter()
+            .position(|segment| matches!(segment, Segment::Wildcard(_)));
+        assert!(
+            wildcard.is_none_or(|i| i == pattern.len() - 1),
+            "a wildcard must be the last segment of the pattern"
+        );
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    pub fn put<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("PUT", pattern, handler)
+    }
+
+    pub fn delete<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("DELETE", pattern, handler)
+    }
+
+    /// The handler for the paths that no route matches, instead of an empty 404.
+    pub fn fallback<F>(&mut self, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Answers the request with the best route for it.
+    /// A path that matches routes with other methods gets a 405 with the Allow header listing them.
+    /// HEAD is answered by the GET route when there isn't a HEAD one, the server then leaves out the body.
+    pub fn handle(&self, mut request: Request) -> Response {
+        let mut best: Option<(&Route, Vec<(String, String)>)> = None;
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            let Some(params) = matches(&route.pattern, &request.path) else {
+                continue;
+            };
+            allowed.push(&route.method);
+            let method_matches = route.method == request.method
+                || (request.method == "HEAD" && route.method == "GET");
+            let better = best.as_ref().is_none_or(|(best, _)| {
+                let ranks =
+                    |route: &Route| route.pattern.iter().map(Segment::rank).collect::<Vec<_>>();
+                // An exact HEAD route beats the GET route of the same pattern.
+                (ranks(route), route.method == request.method)
+                    > (ranks(best), best.method == request.method)
+            });
+            if method_matches && better {
+                best = Some((route, params));
+            }
+        }
+
+        if let Some((route, params)) = best {
+            request.params = params;
+            return (route.handler)(&request);
+        }
+        if allowed.is_empty() {
+            return match &self.fallback {
+                Some(fallback) => fallback(&request),
+                None => Response::new(404).body("Not Found"),
+            };
+        }
+        if allowed.contains(&"GET") {
+            allowed.push("HEAD");
+        }
+        allowed.sort_unstable();
+        allowed.dedup();
+        Response::new(405)
+            .header("Allow", &allowed.join(", "))
+            .body("Method Not Allowed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> Request {
+        let text = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        Request::read(&mut text.as_bytes()).unwrap()
+    }
+
+    fn body(response: Response) -> String {
+        String::from_utf8(response.body_bytes().unwrap().to_vec()).unwrap()
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/users/:id", |request| {
+                Response::new(200).body(format!("user {}", request.param("id").unwrap()))
+            })
+            .get("/users/new", |_| Response::new(200).body("new user form"))
+            .delete("/users/:id", |_| Response::new(200).body("deleted"))
+            .get("/static/*path", |request| {
+                Response::new(200).body(format!("file {}", request.param("path").unwrap()))
+            })
+            .get("/", |_| Response::new(200).body("home"));
+        router
+    }
+
+    #[test]
+    fn routes_with_params_and_wildcards() {
+        let router = router();
+        assert_eq!(body(router.handle(request("GET", "/"))), "home");
+        assert_eq!(body(router.handle(request("GET", "/users/42/"))), "user 42");
+        assert_eq!(
+            body(router.handle(request("GET", "/users/new"))),
+            "new user form"
+        );
+        assert_eq!(
+            body(router.handle(request("DELETE", "/users/42"))),
+            "deleted"
+        );
+        assert_eq!(
+            body(router.handle(request("GET", "/static/css/site.css"))),
+            "file css/site.css"
+        );
+        assert_eq!(body(router.handle(request("HEAD", "/users/7"))), "user 7");
+        assert_eq!(
+            router.handle(request("GET", "/users/42/posts")).status(),
+            404
+        );
+        assert_eq!(router.handle(request("GET", "/nothing")).status(), 404);
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let response = router().handle(request("POST", "/users/42"));
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers().get("Allow"), Some("DELETE, GET, HEAD"));
+
+        let mut router = router();
+        router.fallback(|request| Response::new(404).body(format!("no {}", request.path)));
+        assert_eq!(
+            body(router.handle(request("GET", "/nothing"))),
+            "no /nothing"
+        );
+    }
+}
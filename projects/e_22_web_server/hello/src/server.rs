@@ -0,0 +1,171 @@
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    connection::{handle_connection, KeepAlive},
+    router::Router,
+    shutdown::Shutdown,
+    ThreadPool,
+};
+
+// How often the flag of the Shutdown is checked while the accept loop waits.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The settings of the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Server {
+    pub workers: usize,
+    pub keep_alive: KeepAlive,
+    // How long the requests that are still running get after a shutdown was requested.
+    pub shutdown_deadline: Duration,
+}
+
+impl Server {
+    /// Answers the connections of the listener with the router until the shutdown is requested,
+    /// then waits for the requests in progress (up to the deadline) before returning.
+    pub fn serve(
+        &self,
+        listener: TcpListener,
+        router: Router,
+        shutdown: &Shutdown,
+    ) -> io::Result<()> {
+        let pool = ThreadPool::new(self.workers);
+        let router = Arc::new(router);
+        let waker = wake_on_shutdown(listener.local_addr()?, shutdown.clone());
+
+        // accept blocks until a connection arrives, so the waker connects to us when the shutdown is requested.
+        // This way a new connection doesn't wait for a poll, like it would with a non-blocking listener.
+        for stream in listener.incoming() {
+            if shutdown.is_requested() {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                // Like running out of file descriptors, the next accept can work again.
+                Err(e) => {
+                    eprintln!("Couldn't accept a connection: {e}");
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+            let router = Arc::clone(&router);
+            let shutdown = shutdown.clone();
+            let keep_alive = self.keep_alive;
+
+            pool.execute(move || {
+                handle_connection(stream, &router, keep_alive, &shutdown);
+            });
+        }
+        // Closing the listener refuses the next connections, while the accepted ones are still answered.
+        drop(listener);
+        waker.join().ok();
+
+        println!("Shutting down.");
+        if !pool.shutdown(self.shutdown_deadline) {
+            // A thread can't be stopped from outside in Rust, they end with the process.
+            eprintln!(
+                "Some requests were still running after {:?}, they are abandoned.",
+                self.shutdown_deadline
+            );
+        }
+        Ok(())
+    }
+}
+
+// Waits for the shutdown, then connects to the listener so its accept returns and the loop sees the flag.
+fn wake_on_shutdown(address: SocketAddr, shutdown: Shutdown) -> thread::JoinHandle<()> {
+    // A server listening on every interface (0.0.0.0) can be reached on the loopback one.
+    let mut address = address;
+    if address.ip().is_unspecified() {
+        address.set_ip(match address {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    thread::spawn(move || {
+        while !shutdown.is_requested() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        TcpStream::connect(address).ok();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Response;
+    use std::{
+        io::{Read, Write},
+        time::Instant,
+    };
+
+    fn start(deadline: Duration) -> (SocketAddr, Shutdown, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        let mut router = Router::new();
+        router.get("/sleep/:ms", |request| {
+            let ms = request.param("ms").unwrap().parse().unwrap();
+            thread::sleep(Duration::from_millis(ms));
+            Response::new(200).body("done")
+        });
+        let server = Server {
+            workers: 2,
+            keep_alive: KeepAlive::default(),
+            shutdown_deadline: deadline,
+        };
+        let handle = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || server.serve(listener, router, &shutdown).unwrap())
+        };
+        (address, shutdown, handle)
+    }
+
+    fn send(address: SocketAddr, path: &str) -> TcpStream {
+        let mut client = TcpStream::connect(address).unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        client.write_all(request.as_bytes()).unwrap();
+        client
+    }
+
+    #[test]
+    fn finishes_the_requests_in_progress() {
+        let (address, shutdown, server) = start(Duration::from_secs(5));
+        let mut slow = send(address, "/sleep/300");
+        // An idle keep-alive connection doesn't delay the shutdown.
+        let mut idle = send(address, "/sleep/0");
+        let mut response = [0; 256];
+        assert!(idle.read(&mut response).unwrap() > 0);
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+        shutdown.request();
+        server.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let mut response = String::new();
+        slow.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\nConnection: close\r\n"));
+        assert!(response.ends_with("done"));
+        assert_eq!(idle.read(&mut [0; 16]).unwrap(), 0);
+        // Nobody listens anymore.
+        assert!(TcpStream::connect(address).is_err());
+    }
+
+    #[test]
+    fn gives_up_after_the_deadline() {
+        let (address, shutdown, server) = start(Duration::from_millis(100));
+        let _stuck = send(address, "/sleep/3000");
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+        shutdown.request();
+        server.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::SystemTime,
+};
+
+use crate::{request::Request, response::Response};
+
+// A graceful shutdown stops accepting connections, lets the requests that already arrived finish, and only
+// then stops the workers. Everything that takes part (the accept loop, every connection) checks the same flag.
+//
+// The flag is set by Shutdown::request, which the POST /admin/shutdown handler calls, or by SIGINT (Ctrl+C) and
+// SIGTERM (what `kill` and service managers send). A signal handler runs in the middle of whatever the thread was
+// doing, so the only thing it can safely do is to set an atomic flag, and the server notices it.
+// The standard library can't install signal handlers, and we don't use crates here, so on Unix we declare the
+// C library's `signal` function ourselves (std already links the C library). On other systems only the admin
+// endpoint works and Ctrl+C still stops the process right away.
+
+/// The flag that asks the server to shut down. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+// The flag of the Shutdown that handles the signals, the signal handler can't get it any other way.
+static SIGNALED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Requests the shutdown on SIGINT or SIGTERM. A second signal stops the process right away, without waiting.
+    ///
+    /// Returns false when signals aren't supported (outside Unix), or when another Shutdown already handles them.
+    pub fn on_signals(&self) -> bool {
+        SIGNALED.set(Arc::clone(&self.requested)).is_ok() && signals::install()
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::{os::raw::c_int, sync::atomic::Ordering};
+
+    // The same numbers on Linux and macOS.
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    const SIG_ERR: usize = usize::MAX; // (void (*)(int)) -1
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        // Unlike process::exit, _exit doesn't run any code of the process, so it can be called from a signal handler.
+        fn _exit(status: c_int) -> !;
+    }
+
+    extern "C" fn handle(signum: c_int) {
+        if let Some(flag) = super::SIGNALED.get() {
+            if flag.swap(true, Ordering::SeqCst) {
+                // The second Ctrl+C: the user doesn't want to wait. 128 + the signal is what shells report.
+                unsafe { _exit(128 + signum) }
+            }
+        }
+    }
+
+    pub(super) fn install() -> bool {
+        // SAFETY: `handle` only touches an atomic flag (and calls _exit), which is allowed in a signal handler.
+        unsafe { signal(SIGINT, handle) != SIG_ERR && signal(SIGTERM, handle) != SIG_ERR }
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub(super) fn install() -> bool {
+        false
+    }
+}
+
+/// The header that must carry the token of the admin endpoint.
+pub const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+/// A token that can't be guessed, for the admin endpoint: 128 random bits in hexadecimal.
+///
+/// We don't use crates, so the randomness comes from RandomState, the keys std generates for HashMap.
+pub fn random_token() -> String {
+    (0..2)
+        .map(|_| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |time| time.as_nanos()),
+            );
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+// Compares every byte even after a difference, so the time of the answer doesn't tell how much of the token was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// The handler of POST /admin/shutdown.
+///
+/// Being on the same machine isn't enough: any web page open in a local browser can send a request to 127.0.0.1.
+/// So the request must also be a POST with the token given at startup in the X-Admin-Token header.
+/// A page can't send a custom header to another origin without a CORS preflight (an OPTIONS request),
+/// and this server never allows one, so only a client like curl that knows the token gets through.
+pub fn admin_handler(
+    shutdown: Shutdown,
+    token: String,
+) -> impl Fn(&Request) -> Response + Send + Sync + 'static {
+    move |request| {
+        let local = request
+            .remote_addr
+            .is_some_and(|address| address.ip().is_loopback());
+        let authorized = request
+            .headers
+            .get(ADMIN_TOKEN_HEADER)
+            .is_some_and(|given| same_token(given, &token));
+        if request.method != "POST" || !local || !authorized {
+            return Response::new(403).body("Forbidden");
+        }
+        shutdown.request();
+        Response::new(202)
+            .header("Connection", "close")
+            .body("Shutting down")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+
+    #[test]
+    fn admin_endpoint_needs_a_local_client_and_the_token() {
+        let shutdown = Shutdown::new();
+        let token = random_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, random_token());
+        let mut router = Router::new();
+        router.post(
+            "/admin/shutdown",
+            admin_handler(shutdown.clone(), token.clone()),
+        );
+        let read = |headers: &str| {
+            let text = format!("POST /admin/shutdown HTTP/1.1\r\nHost: x\r\n{headers}\r\n");
+            let mut request = Request::read(&mut text.as_bytes()).unwrap();
+            request.remote_addr = Some("127.0.0.1:50000".parse().unwrap());
+            request
+        };
+
+        // What a web page can send: no token, or a wrong one.
+        assert_eq!(router.handle(read("")).status(), 403);
+        let wrong = format!("X-Admin-Token: {}\r\n", random_token());
+        assert_eq!(router.handle(read(&wrong)).status(), 403);
+        let mut request = read(&format!("X-Admin-Token: {token}\r\n"));
+        request.remote_addr = Some("192.168.1.20:50000".parse().unwrap());
+        assert_eq!(router.handle(request.clone()).status(), 403);
+        assert!(!shutdown.is_requested());
+
+        request.remote_addr = Some("127.0.0.1:50000".parse().unwrap());
+        assert_eq!(router.handle(request).status(), 202);
+        assert!(shutdown.is_requested());
+        // Clones share the flag.
+        assert!(shutdown.clone().is_requested());
+    }
+}
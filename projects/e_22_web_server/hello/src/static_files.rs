@@ -0,0 +1,226 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{request::Request, response::Response};
+
+// Serves the files of a directory, the document root. The path of the request is a path inside the root,
+// so "/static/css/site.css" mounted at "/static/*path" reads <root>/css/site.css.
+//
+// The client chooses the path, so it must never get out of the root:
+// - A ".." segment is refused, even when it was percent-encoded ("%2e%2e"), because the request path is already decoded.
+// - Hidden files and directories (".git", ".env") aren't served.
+// - A symbolic link inside the root could point anywhere, so the real path of the file must still be inside the root.
+
+/// The Content-Type of a file from its extension, the browsers need it to know what to do with the bytes.
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        // Unknown bytes are downloaded instead of being shown as something they aren't.
+        _ => "application/octet-stream",
+    }
+}
+
+/// A handler for the files of a document root.
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Answers with the file the request asks for.
+    ///
+    /// The path inside the root is the `path` parameter when the route is like "/static/*path",
+    /// or the whole path of the request when it is used as the fallback of the router.
+    pub fn serve(&self, request: &Request) -> Response {
+        let path = request.param("path").unwrap_or(&request.path);
+        let mut file_path = self.root.clone();
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            // A backslash is a separator on Windows, and "C:" would start another drive.
+            if segment == ".." || segment.contains(['\\', ':', '\0']) {
+                return Response::new(403).body("Forbidden");
+            }
+            if segment.starts_with('.') {
+                return not_found();
+            }
+            file_path.push(segment);
+        }
+
+        let metadata = match fs::metadata(&file_path) {
+            Ok(metadata) => metadata,
+            Err(e) => return error(e),
+        };
+        if metadata.is_dir() {
+            // The relative links of the index.html are relative to the directory only when the URL ends with '/'.
+            if !request.path.ends_with('/') {
+                let location = directory_location(&request.target);
+                return Response::new(301)
+                    .header("Location", &location)
+                    .body(format!("Moved to {location}"));
+            }
+            file_path.push("index.html");
+        }
+
+        match self.inside_root(&file_path) {
+            Ok(true) => {}
+            Ok(false) => return Response::new(403).body("Forbidden"),
+            Err(e) => return error(e),
+        }
+        // The length comes from the opened file, so it is the size of the file that is sent.
+        let file = match File::open(&file_path) {
+            Ok(file) => file,
+            Err(e) => return error(e),
+        };
+        match file.metadata() {
+            Ok(metadata) if metadata.is_file() => {
+                let length = metadata.len();
+                Response::new(200)
+                    .header("Content-Type", content_type(&file_path))
+                    .file(file, length)
+            }
+            Ok(_) => not_found(),
+            Err(e) => error(e),
+        }
+    }
+
+    // Whether the real path of the file, once the symbolic links are followed, is still inside the root.
+    fn inside_root(&self, path: &Path) -> io::Result<bool> {
+        Ok(path.canonicalize()?.starts_with(self.root.canonicalize()?))
+    }
+}
+
+fn not_found() -> Response {
+    Response::new(404).body("Not Found")
+}
+
+// The URL of the directory with a trailing '/', from the target as it was sent: request.path is decoded,
+// so "/my%20docs" would become "/my docs/", and it doesn't have the query.
+fn directory_location(target: &str) -> String {
+    let target = target.split('#').next().unwrap_or("");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    // A request to a proxy has the whole URL, only its path is kept.
+    let path = match path.find("://") {
+        Some(scheme) => {
+            let after = &path[scheme + 3..];
+            after.find('/').map_or("/", |slash| &after[slash..])
+        }
+        None => path,
+    };
+    let mut location = String::new();
+    // The escapes of the client stay as they are, only what can't be in a header is encoded, like "é".
+    for b in path.bytes() {
+        if b.is_ascii_graphic() {
+            location.push(b as char);
+        } else {
+            location += &format!("%{b:02X}");
+        }
+    }
+    location.push('/');
+    if !query.is_empty() {
+        location.push('?');
+        location += query;
+    }
+    location
+}
+
+fn error(e: io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::NotFound => not_found(),
+        io::ErrorKind::PermissionDenied => Response::new(403).body("Forbidden"),
+        // A file named "a.txt/b" doesn't exist, even if a.txt does.
+        io::ErrorKind::NotADirectory => not_found(),
+        _ => Response::new(500).body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use std::{env, process};
+
+    fn get(router: &Router, path: &str) -> (u16, String, Vec<u8>) {
+        let text = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let response = router.handle(Request::read(&mut text.as_bytes()).unwrap());
+        let mut written = Vec::new();
+        response.write_to(&mut written, true).unwrap();
+        let end = written.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(written[..end].to_vec()).unwrap();
+        (response.status(), head, written[end + 4..].to_vec())
+    }
+
+    #[test]
+    fn serves_files_from_the_root() {
+        let dir = env::temp_dir().join(format!("hello-static-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("public");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::create_dir_all(root.join("my docs")).unwrap();
+        fs::write(root.join("index.html"), "<h1>Home</h1>").unwrap();
+        fs::write(root.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+        fs::write(root.join("logo.PNG"), [0x89, b'P', b'N', b'G', 0, 0xff]).unwrap();
+        fs::write(root.join(".env"), "SECRET=1").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        let mut router = Router::new();
+        let files = StaticFiles::new(&root);
+        router.get("/static/*path", move |request| files.serve(request));
+
+        let (status, head, body) = get(&router, "/static/logo.PNG");
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: image/png"));
+        assert!(head.contains("Content-Length: 6"));
+        assert_eq!(body, [0x89, b'P', b'N', b'G', 0, 0xff]);
+
+        let (status, _, body) = get(&router, "/static/");
+        assert_eq!((status, body), (200, b"<h1>Home</h1>".to_vec()));
+        let (status, head, _) = get(&router, "/static/docs");
+        assert_eq!(status, 301);
+        assert!(head.contains("Location: /static/docs/"));
+        let (status, head, _) = get(&router, "/static/my%20docs?lang=en&q=a%26b");
+        assert_eq!(status, 301);
+        assert!(head.contains("Location: /static/my%20docs/?lang=en&q=a%26b\r\n"));
+        assert_eq!(get(&router, "/static/docs/").2, b"<h1>Docs</h1>");
+
+        assert_eq!(get(&router, "/static/missing.css").0, 404);
+        assert_eq!(get(&router, "/static/index.html/x").0, 404);
+        assert_eq!(get(&router, "/static/.env").0, 404);
+        assert_eq!(get(&router, "/static/../secret.txt").0, 403);
+        assert_eq!(get(&router, "/static/%2e%2e/secret.txt").0, 403);
+        assert_eq!(get(&router, "/static/docs/..%2f..%2fsecret.txt").0, 403);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link.txt")).unwrap();
+            assert_eq!(get(&router, "/static/link.txt").0, 403);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}